@@ -0,0 +1,249 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{AppState, Workstation, WorkstationStatus};
+use crate::helpers::{
+    remove_workstation,
+    unix_now,
+};
+use crate::suspend::suspend_workstation;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const REAPER_INTERVAL_SECS: u64 = 15;
+
+struct ActivityState {
+    last_active: u64,
+    warned: bool,
+}
+
+// Remembers when each workstation was last used and relays expiry notices to connected terminals.
+pub struct ActivityTracker {
+    workstations: Mutex<HashMap<String, ActivityState>>,
+    // Open terminals, execs, tunnels, proxied upgrades and forwarded connections per workstation.
+    sessions: Mutex<HashMap<String, u32>>,
+    notices: broadcast::Sender<(String, String)>,
+}
+
+// Keeps a workstation counted as in use until the session it was opened for ends.
+pub struct SessionGuard {
+    activity: Arc<ActivityTracker>,
+    workstation_id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.activity.close_session(&self.workstation_id);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExtendPayload {
+    pub seconds: Option<u64>,
+}
+
+impl ActivityTracker {
+    pub fn new() -> Self {
+        let (notices, _) = broadcast::channel(64);
+
+        ActivityTracker {
+            workstations: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            notices,
+        }
+    }
+
+    pub fn touch(&self, workstation_id: &str) {
+        let mut workstations = self.workstations.lock().unwrap();
+        workstations.insert(workstation_id.to_string(), ActivityState { last_active: unix_now(), warned: false });
+    }
+
+    pub fn last_active(&self, workstation_id: &str) -> Option<u64> {
+        self.workstations.lock().unwrap().get(workstation_id).map(|activity| activity.last_active)
+    }
+
+    pub fn forget(&self, workstation_id: &str) {
+        self.workstations.lock().unwrap().remove(workstation_id);
+    }
+
+    pub fn open_session(self: &Arc<Self>, workstation_id: &str) -> SessionGuard {
+        self.touch(workstation_id);
+        *self.sessions.lock().unwrap().entry(workstation_id.to_string()).or_insert(0) += 1;
+
+        SessionGuard {
+            activity: Arc::clone(self),
+            workstation_id: workstation_id.to_string(),
+        }
+    }
+
+    // The session's end counts as activity, unless the workstation has been forgotten in the meantime.
+    fn close_session(&self, workstation_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(count) = sessions.get_mut(workstation_id) {
+            *count -= 1;
+            if *count == 0 {
+                sessions.remove(workstation_id);
+            }
+        }
+
+        if let Some(activity) = self.workstations.lock().unwrap().get_mut(workstation_id) {
+            activity.last_active = unix_now();
+            activity.warned = false;
+        }
+    }
+
+    pub fn in_session(&self, workstation_id: &str) -> bool {
+        self.sessions.lock().unwrap().contains_key(workstation_id)
+    }
+
+    // Returns true the first time it is called for the current deadline.
+    fn mark_warned(&self, workstation_id: &str) -> bool {
+        let mut workstations = self.workstations.lock().unwrap();
+        match workstations.get_mut(workstation_id) {
+            Some(activity) if !activity.warned => {
+                activity.warned = true;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn notify(&self, workstation_id: &str, message: String) {
+        let _ = self.notices.send((workstation_id.to_string(), message));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(String, String)> {
+        self.notices.subscribe()
+    }
+}
+
+/*----------------------------------------------------------REAPER----------------------------------------------------------*/
+
+fn deadline(state: &AppState, workstation: &Workstation) -> Option<u64> {
+    let idle_deadline = workstation.idle_timeout.and_then(|timeout| {
+        state.activity.last_active(&workstation.id).map(|last_active| last_active + timeout)
+    });
+
+    [workstation.expires_at, idle_deadline].into_iter().flatten().min()
+}
+
+async fn expire_workstation(state: AppState, workstation_id: String) {
+    info!("Workstation {} expired. Deleting...", workstation_id);
+
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let workstation_id = workstation_id.clone();
+        move || remove_workstation(&state, &workstation_id)
+    }).await;
+
+    match result {
+        Ok(Ok(_)) => info!("Expired workstation {} deleted", workstation_id),
+        Ok(Err(e)) => error!("Failed to delete expired workstation {}: {}", workstation_id, e),
+        Err(e) => error!("Expiry task for workstation {} panicked: {}", workstation_id, e),
+    }
+}
+
+pub async fn run_reaper(state: AppState) {
+    info!("Checking workstation expiry every {} seconds", REAPER_INTERVAL_SECS);
+
+    let mut interval = tokio::time::interval(Duration::from_secs(REAPER_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let now = unix_now();
+        let workstations = state.workstations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+
+        for workstation in workstations {
+            // A workstation with an open session is in use, however long it has been since the last input.
+            if state.activity.in_session(&workstation.id) {
+                continue;
+            }
+
+            if workstation.status == WorkstationStatus::Running {
+                let idle_since = state.activity.last_active(&workstation.id).unwrap_or(now);
+
+                if state.config.suspend_idle_secs.is_some_and(|secs| now >= idle_since + secs) {
+                    info!("Workstation {} is idle. Suspending...", workstation.id);
+                    if let Err(e) = suspend_workstation(&state, &workstation.id).await {
+                        error!("Failed to suspend idle workstation {}: {}", workstation.id, e);
+                    }
+                    continue;
+                }
+            }
+
+            let Some(deadline) = deadline(&state, &workstation) else {
+                continue;
+            };
+
+            if now >= deadline {
+                expire_workstation(state.clone(), workstation.id.clone()).await;
+            } else if now + state.config.expiry_warning_secs >= deadline && state.activity.mark_warned(&workstation.id) {
+                state.activity.notify(&workstation.id, format!(
+                    "\r\n*** This workstation will be shut down in {} seconds. Use it or extend it to keep it running. ***\r\n",
+                    deadline - now
+                ));
+            }
+        }
+    }
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn extend_workstation_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    payload: Option<Json<ExtendPayload>>,
+) -> impl IntoResponse {
+    info!("Extend request received for ID: {}", workstation_id);
+
+    let mut workstations_map = state.workstations.lock().unwrap();
+
+    let Some(workstation) = workstations_map.get_mut(&workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    if let Some(expires_at) = workstation.expires_at {
+        let seconds = payload.and_then(|Json(payload)| payload.seconds).or(workstation.ttl).unwrap_or(0);
+        workstation.expires_at = Some(expires_at.max(unix_now()) + seconds);
+    }
+
+    state.activity.touch(&workstation_id);
+
+    (StatusCode::OK, Json(workstation.clone())).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_keep_a_workstation_in_use_until_the_last_one_ends() {
+        let activity = Arc::new(ActivityTracker::new());
+
+        let terminal = activity.open_session("ws");
+        let exec = activity.open_session("ws");
+        assert!(activity.in_session("ws"));
+
+        drop(terminal);
+        assert!(activity.in_session("ws"));
+
+        drop(exec);
+        assert!(!activity.in_session("ws"));
+        assert!(activity.last_active("ws").is_some());
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    process::Command,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use tracing::info;
+
+use crate::Workstation;
+use crate::capacity::host_cpus;
+use crate::helpers::firecracker_pid;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+// Firecracker names vCPU threads `fc_vcpu <index>`; every other thread belongs to the VMM.
+const VCPU_THREAD_PREFIX: &str = "fc_vcpu ";
+const THREAD_WAIT_ATTEMPTS: u32 = 20;
+const ONLINE_CPUS_PATH: &str = "/sys/devices/system/cpu/online";
+
+// Host cores a workstation is pinned to: `vcpus[i]` runs vCPU i, `vmm` takes the API and device threads.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct CpuAffinity {
+    pub vcpus: Vec<u32>,
+    #[serde(default)]
+    pub vmm: Vec<u32>,
+}
+
+impl CpuAffinity {
+    pub fn cores(&self) -> impl Iterator<Item = u32> + '_ {
+        self.vcpus.iter().chain(self.vmm.iter()).copied()
+    }
+}
+
+// Hands out host cores exclusively: a core pinned by one workstation cannot be pinned by another.
+pub struct CoreAllocator {
+    cores: Mutex<HashMap<u32, String>>,
+}
+
+impl CoreAllocator {
+    pub fn new() -> Self {
+        CoreAllocator {
+            cores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Reserves every core of the affinity or none; on conflict returns the cores already taken.
+    pub fn reserve(&self, workstation_id: &str, affinity: &CpuAffinity) -> Result<(), Vec<u32>> {
+        let mut cores = self.cores.lock().unwrap();
+
+        let mut taken = affinity.cores().filter(|core| cores.contains_key(core)).collect::<Vec<_>>();
+        if !taken.is_empty() {
+            taken.sort_unstable();
+            taken.dedup();
+            return Err(taken);
+        }
+
+        for core in affinity.cores() {
+            cores.insert(core, workstation_id.to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn release(&self, workstation_id: &str) {
+        self.cores.lock().unwrap().retain(|_, owner| owner != workstation_id);
+    }
+}
+
+// The kernel's list of online CPUs, e.g. `0-3,8-11`; offline or isolated-away cores can be missing from it.
+pub fn online_cpu_list() -> String {
+    fs::read_to_string(ONLINE_CPUS_PATH)
+        .map(|list| list.trim().to_string())
+        .unwrap_or_else(|_| format!("0-{}", host_cpus().saturating_sub(1)))
+}
+
+pub fn parse_cpu_list(list: &str) -> Result<Vec<u32>, String> {
+    let mut cpus = Vec::new();
+
+    for range in list.split(',').map(str::trim).filter(|range| !range.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let first = first.parse::<u32>().map_err(|_| format!("Invalid CPU list entry: {}", range))?;
+        let last = last.parse::<u32>().map_err(|_| format!("Invalid CPU list entry: {}", range))?;
+        if first > last {
+            return Err(format!("Invalid CPU list entry: {}", range));
+        }
+        cpus.extend(first..=last);
+    }
+
+    Ok(cpus)
+}
+
+/*----------------------------------------------------------PINNING----------------------------------------------------------*/
+
+fn thread_names(pid: u32) -> Vec<(u32, String)> {
+    let Ok(tasks) = fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return Vec::new();
+    };
+
+    tasks
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|tid| {
+            let name = fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, tid)).ok()?;
+            Some((tid, name.trim().to_string()))
+        })
+        .collect()
+}
+
+fn set_affinity(tid: u32, cores: &[u32]) -> Result<(), String> {
+    let core_list = cores.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+
+    let taskset = Command::new("taskset")
+        .args(["-p", "-c", &core_list, &tid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .status();
+
+    if taskset.is_err() || !taskset.unwrap().success() {
+        return Err(format!("Failed to pin thread {} to cores {}", tid, core_list));
+    }
+
+    Ok(())
+}
+
+// Pins the running VM's threads; must be repeated whenever a new Firecracker process takes over the VM.
+pub fn pin_threads(workstation: &Workstation) -> Result<(), String> {
+    let Some(affinity) = &workstation.cpu_affinity else {
+        return Ok(());
+    };
+
+    let pid = firecracker_pid(workstation)
+        .ok_or(format!("Firecracker process for VM ID {} not found", workstation.vm_id))?;
+
+    // vCPU threads are started with the instance, so give them a moment to appear.
+    let mut threads = thread_names(pid);
+    for _ in 0..THREAD_WAIT_ATTEMPTS {
+        let vcpu_threads = threads.iter().filter(|(_, name)| name.starts_with(VCPU_THREAD_PREFIX)).count();
+        if vcpu_threads >= affinity.vcpus.len() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+        threads = thread_names(pid);
+    }
+
+    for (tid, name) in threads {
+        match name.strip_prefix(VCPU_THREAD_PREFIX) {
+            Some(index) => {
+                let index = index.parse::<usize>()
+                    .map_err(|_| format!("Unexpected vCPU thread name: {}", name))?;
+                let core = affinity.vcpus.get(index)
+                    .ok_or(format!("No core given for vCPU {} of VM ID {}", index, workstation.vm_id))?;
+                set_affinity(tid, &[*core])?;
+            },
+            None if !affinity.vmm.is_empty() => set_affinity(tid, &affinity.vmm)?,
+            None => {},
+        }
+    }
+
+    info!("Pinned VM ID: {} vCPUs to cores {:?}, VMM threads to {:?}", workstation.vm_id, affinity.vcpus, affinity.vmm);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn affinity(vcpus: &[u32], vmm: &[u32]) -> CpuAffinity {
+        CpuAffinity { vcpus: vcpus.to_vec(), vmm: vmm.to_vec() }
+    }
+
+    #[test]
+    fn conflicting_reservations_are_rejected_whole() {
+        let cores = CoreAllocator::new();
+
+        assert!(cores.reserve("a", &affinity(&[0, 1], &[2])).is_ok());
+        assert_eq!(cores.reserve("b", &affinity(&[3, 2], &[1])), Err(vec![1, 2]));
+
+        // Nothing of the rejected request was kept, so its free core is still available.
+        assert!(cores.reserve("c", &affinity(&[3], &[])).is_ok());
+    }
+
+    #[test]
+    fn released_cores_can_be_reserved_again() {
+        let cores = CoreAllocator::new();
+
+        assert!(cores.reserve("a", &affinity(&[0], &[1])).is_ok());
+        cores.release("a");
+
+        assert!(cores.reserve("b", &affinity(&[1], &[0])).is_ok());
+    }
+
+    #[test]
+    fn parses_cpu_lists() {
+        assert_eq!(parse_cpu_list("0-3,8\n"), Ok(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cpu_list("5"), Ok(vec![5]));
+        assert_eq!(parse_cpu_list(""), Ok(vec![]));
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("0-x").is_err());
+    }
+}
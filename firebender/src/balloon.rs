@@ -0,0 +1,97 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{AppState, Workstation, WorkstationStatus};
+use crate::helpers::{
+    firecracker_api,
+    firecracker_get,
+    firecracker_socket_path,
+};
+use crate::suspend::ensure_running;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const DEFAULT_STATS_POLLING_SECS: u32 = 5;
+
+fn default_stats_polling() -> u32 {
+    DEFAULT_STATS_POLLING_SECS
+}
+
+// virtio-balloon settings; `amount_mib` is the memory the balloon currently asks the guest to give back.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Balloon {
+    #[serde(default)]
+    pub amount_mib: u32,
+    #[serde(default)]
+    pub deflate_on_oom: bool,
+    #[serde(default = "default_stats_polling")]
+    pub stats_polling_interval_s: u32,
+}
+
+/*----------------------------------------------------------BALLOON----------------------------------------------------------*/
+
+// Attaches the balloon device; must happen before boot.
+pub fn configure_balloon(socket_path: &str, balloon: &Balloon) -> Result<(), String> {
+    firecracker_api(socket_path, "PUT", "/balloon", &serde_json::to_string(balloon).unwrap())
+}
+
+pub fn update_balloon_target(workstation: &Workstation, target_mib: u32) -> Result<(), String> {
+    let set_target = serde_json::json!({ "amount_mib": target_mib });
+
+    firecracker_api(&firecracker_socket_path(workstation), "PATCH", "/balloon", &set_target.to_string())
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn balloon_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+) -> impl IntoResponse {
+    info!("Balloon statistics request received for ID: {}", workstation_id);
+
+    let Some(workstation) = state.workstations.lock().unwrap().get(&workstation_id).cloned() else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    let Some(balloon) = workstation.balloon else {
+        return (StatusCode::CONFLICT, Json(serde_json::json!({"error": format!("Workstation with ID {} has no balloon device", workstation_id)}))).into_response();
+    };
+
+    // Reading statistics is no reason to wake a suspended VM; its balloon has nothing to report while paused.
+    if matches!(workstation.status, WorkstationStatus::Suspending | WorkstationStatus::Suspended) {
+        return (StatusCode::OK, Json(serde_json::json!({ "balloon": balloon, "statistics": Value::Null }))).into_response();
+    }
+
+    if let Err(e) = ensure_running(&state, &workstation_id).await {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"error": e}))).into_response();
+    }
+
+    // Statistics are only collected when polling is enabled.
+    let statistics = if balloon.stats_polling_interval_s > 0 {
+        let socket_path = firecracker_socket_path(&workstation);
+        match tokio::task::spawn_blocking(move || firecracker_get(&socket_path, "/balloon/statistics")).await {
+            Ok(Ok(statistics)) => statistics,
+            Ok(Err(e)) => {
+                error!("{}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+            },
+            Err(e) => {
+                error!("Balloon statistics task for workstation {} panicked: {}", workstation_id, e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "Failed to read balloon statistics"}))).into_response();
+            }
+        }
+    } else {
+        Value::Null
+    };
+
+    (StatusCode::OK, Json(serde_json::json!({ "balloon": balloon, "statistics": statistics }))).into_response()
+}
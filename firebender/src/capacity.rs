@@ -0,0 +1,239 @@
+use axum::http::StatusCode;
+use serde::Serialize;
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    process::Command,
+    thread,
+};
+use tracing::error;
+
+use crate::{
+    AppState,
+    CreateWorkstationPayload,
+    IMAGE_PATH,
+    KERNEL_IMAGE_PATH,
+    ROOTFS_IMAGE_PATH,
+};
+use crate::helpers::rootfs_path;
+use crate::volumes::volume_path;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+pub const BYTES_PER_GIB: u64 = 1024 * 1024 * 1024;
+
+#[derive(Serialize)]
+pub struct Shortage {
+    pub resource: &'static str,
+    pub unit: &'static str,
+    pub requested: u64,
+    pub available: u64,
+    #[serde(skip)]
+    pub status: StatusCode,
+}
+
+impl Shortage {
+    pub fn message(&self) -> String {
+        format!(
+            "Not enough host {} for this workstation: requested {} {}, {} {} available",
+            self.resource, self.requested, self.unit, self.available, self.unit
+        )
+    }
+}
+
+/*----------------------------------------------------------HOST----------------------------------------------------------*/
+
+pub fn host_cpus() -> u64 {
+    thread::available_parallelism().map(|n| n.get() as u64).unwrap_or(1)
+}
+
+fn host_memory_mib() -> Result<u64, String> {
+    let meminfo = fs::read_to_string("/proc/meminfo")
+        .map_err(|e| format!("Failed to read /proc/meminfo: {}", e))?;
+
+    meminfo.lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(|kib| kib / 1024)
+        .ok_or("Failed to parse MemTotal from /proc/meminfo".to_string())
+}
+
+fn free_disk_bytes(path: &str) -> Result<u64, String> {
+    let output = Command::new("df")
+        .args(["--output=avail", "-B1", path])
+        .output()
+        .map_err(|e| format!("Failed to run df on {}: {}", path, e))?;
+
+    if !output.status.success() {
+        return Err(format!("Failed to query free disk space on {}", path));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .nth(1)
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .ok_or(format!("Failed to parse free disk space on {}", path))
+}
+
+fn image_bytes(path: &str) -> u64 {
+    fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)
+}
+
+pub fn rootfs_image_bytes() -> u64 {
+    image_bytes(ROOTFS_IMAGE_PATH)
+}
+
+// How far a sparse image can still grow before it takes up its full size on disk.
+fn unallocated_bytes(path: &str) -> u64 {
+    fs::metadata(path).map(|metadata| metadata.len().saturating_sub(metadata.blocks() * 512)).unwrap_or(0)
+}
+
+// Space the existing rootfs copies and volumes will take once their guests fill them, on top of what they use now.
+fn committed_disk_bytes(state: &AppState) -> u64 {
+    let mut workstations = state.workstations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+    workstations.extend(state.warm_pool.workstations());
+
+    let volume_ids = state.volumes.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+
+    let rootfs_bytes: u64 = workstations
+        .iter()
+        .filter(|w| !w.read_only)
+        .map(|w| unallocated_bytes(&rootfs_path(w)))
+        .sum();
+
+    let volume_bytes: u64 = volume_ids
+        .iter()
+        .map(|id| unallocated_bytes(&volume_path(id)))
+        .sum();
+
+    rootfs_bytes + volume_bytes
+}
+
+// Writable workstations get their own copy of the kernel and rootfs under IMAGE_PATH. A grown rootfs
+// starts out sparse, but counts at its full size.
+fn image_copy_bytes(payload: &CreateWorkstationPayload) -> u64 {
+    if payload.read_only {
+        return 0;
+    }
+
+    let rootfs_bytes = payload.disk_size_gib.map_or(0, |gib| gib * BYTES_PER_GIB).max(rootfs_image_bytes());
+
+    image_bytes(KERNEL_IMAGE_PATH) + rootfs_bytes
+}
+
+/*----------------------------------------------------------ADMISSION----------------------------------------------------------*/
+
+// What is left of a host resource once the reserve is set aside, overcommit applied and existing VMs counted.
+fn available(host: u64, reserved: u64, overcommit_ratio: f64, committed: u64) -> u64 {
+    let limit = (host.saturating_sub(reserved) as f64 * overcommit_ratio) as u64;
+    limit.saturating_sub(committed)
+}
+
+// Must be called while holding `vm_counter` so concurrent creations see each other's commitments.
+pub fn check_capacity(state: &AppState, payload: &CreateWorkstationPayload) -> Result<(), Shortage> {
+    let config = &state.config;
+
+    let mut committed = state.workstations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+    committed.extend(state.warm_pool.workstations());
+
+    let (committed_vcpus, committed_memory_mib) = committed
+        .iter()
+        .fold((0, 0), |(vcpus, memory), w| (vcpus + w.vcpu_count, memory + w.mem_size_mib as u64));
+
+    let cpus_available = available(host_cpus(), config.reserved_cpus, config.cpu_overcommit_ratio, committed_vcpus);
+
+    if payload.vcpu_count > cpus_available {
+        return Err(Shortage {
+            resource: "cpu",
+            unit: "vCPUs",
+            requested: payload.vcpu_count,
+            available: cpus_available,
+            status: StatusCode::CONFLICT,
+        });
+    }
+
+    match host_memory_mib() {
+        Ok(memory_mib) => {
+            let memory_available = available(memory_mib, config.reserved_memory_mib, config.memory_overcommit_ratio, committed_memory_mib);
+
+            if payload.mem_size_mib as u64 > memory_available {
+                return Err(Shortage {
+                    resource: "memory",
+                    unit: "MiB",
+                    requested: payload.mem_size_mib as u64,
+                    available: memory_available,
+                    status: StatusCode::CONFLICT,
+                });
+            }
+        },
+        Err(e) => error!("Skipping memory admission check: {}", e),
+    }
+
+    check_disk_space(state, image_copy_bytes(payload))
+}
+
+// Checks that `disk_needed` bytes fit in IMAGE_PATH on top of the configured reserve and the room existing
+// sparse disks still need to grow into.
+pub fn check_disk_space(state: &AppState, disk_needed: u64) -> Result<(), Shortage> {
+    if disk_needed == 0 {
+        return Ok(());
+    }
+
+    match free_disk_bytes(IMAGE_PATH) {
+        Ok(free_bytes) => {
+            let disk_available = available(free_bytes, state.config.reserved_disk_mib * 1024 * 1024, 1.0, committed_disk_bytes(state));
+
+            if disk_needed > disk_available {
+                return Err(Shortage {
+                    resource: "disk",
+                    unit: "bytes",
+                    requested: disk_needed,
+                    available: disk_available,
+                    status: StatusCode::INSUFFICIENT_STORAGE,
+                });
+            }
+        },
+        Err(e) => error!("Skipping disk admission check: {}", e),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_comes_off_before_overcommit() {
+        // 16 CPUs, 1 reserved, 4x overcommit: 60 vCPUs in total.
+        assert_eq!(available(16, 1, 4.0, 0), 60);
+        assert_eq!(available(16, 1, 4.0, 58), 2);
+    }
+
+    #[test]
+    fn fractional_overcommit_rounds_down() {
+        assert_eq!(available(8192, 1024, 1.5, 0), 10752);
+        assert_eq!(available(3, 0, 0.5, 0), 1);
+    }
+
+    #[test]
+    fn sparse_images_count_their_unwritten_size() {
+        let path = std::env::temp_dir().join(format!("firebender-sparse-{}", uuid::Uuid::new_v4()));
+        let file = fs::File::create(&path).unwrap();
+        file.set_len(BYTES_PER_GIB).unwrap();
+        drop(file);
+
+        let unallocated = unallocated_bytes(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(unallocated, BYTES_PER_GIB);
+        assert_eq!(unallocated_bytes("/nonexistent/firebender.ext4"), 0);
+    }
+
+    #[test]
+    fn overcommitted_hosts_have_nothing_left() {
+        assert_eq!(available(16, 1, 1.0, 20), 0);
+        assert_eq!(available(1, 4, 4.0, 0), 0);
+    }
+}
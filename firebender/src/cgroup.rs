@@ -0,0 +1,184 @@
+use serde::Serialize;
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    path::Path,
+    thread,
+    time::Duration,
+};
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{
+    CreateWorkstationPayload,
+    Workstation,
+    IMAGE_PATH,
+};
+use crate::config::Config;
+use crate::validation::BYTES_PER_MBIT;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+pub const CGROUP_SLICE: &str = "firebender.slice";
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "io"];
+
+const CPU_PERIOD_US: u64 = 100000;
+// Memory the VMM itself needs on top of guest RAM before the cgroup starts reclaiming.
+const VMM_MEMORY_OVERHEAD_MIB: u64 = 128;
+// The VMM has already exited by the time the cgroup is removed, so only a short wait is needed for stragglers.
+const REMOVE_ATTEMPTS: u32 = 10;
+const REMOVE_RETRY_MS: u64 = 20;
+
+pub const DEFAULT_CPU_WEIGHT: u64 = 100;
+pub const MAX_CPU_WEIGHT: u64 = 10000;
+
+// Limits applied to the cgroup holding a workstation's Firecracker process.
+#[derive(Serialize, Clone)]
+pub struct CgroupLimits {
+    pub cpu_weight: u64,
+    pub cpu_quota_us: u64,
+    pub memory_max_bytes: u64,
+    pub io_max_bps: Option<u64>,
+    pub io_max_iops: Option<u64>,
+}
+
+impl CgroupLimits {
+    // One extra CPU of quota covers the VMM and I/O threads next to the vCPUs.
+    pub fn new(vcpu_count: u64, mem_size_mib: u32, cpu_shares: Option<u64>) -> Self {
+        CgroupLimits {
+            cpu_weight: cpu_shares.unwrap_or(DEFAULT_CPU_WEIGHT),
+            cpu_quota_us: (vcpu_count + 1) * CPU_PERIOD_US,
+            memory_max_bytes: (mem_size_mib as u64 + VMM_MEMORY_OVERHEAD_MIB) * 1024 * 1024,
+            io_max_bps: None,
+            io_max_iops: None,
+        }
+    }
+
+    pub fn for_payload(config: &Config, payload: &CreateWorkstationPayload) -> Self {
+        CgroupLimits {
+            io_max_bps: config.io_mbps_per_vcpu.map(|mbps| mbps * payload.vcpu_count * BYTES_PER_MBIT),
+            io_max_iops: config.io_iops_per_vcpu.map(|iops| iops * payload.vcpu_count),
+            ..CgroupLimits::new(payload.vcpu_count, payload.mem_size_mib, payload.cpu_shares)
+        }
+    }
+}
+
+/*----------------------------------------------------------CGROUPS----------------------------------------------------------*/
+
+pub fn cgroup_path(vm_id: &str) -> String {
+    format!("{}/{}/{}", CGROUP_ROOT, CGROUP_SLICE, vm_id)
+}
+
+fn cgroup_v2_available() -> bool {
+    Path::new(&format!("{}/cgroup.controllers", CGROUP_ROOT)).exists()
+}
+
+fn write_cgroup_file(vm_id: &str, file: &str, value: &str) -> Result<(), String> {
+    fs::write(format!("{}/{}", cgroup_path(vm_id), file), value)
+        .map_err(|e| format!("Failed to set {} to {} for VM ID {}: {}", file, value, vm_id, e))
+}
+
+// Controllers have to be delegated down from the root before the slice's children can use them.
+fn enable_controllers() {
+    for parent in [CGROUP_ROOT.to_string(), format!("{}/{}", CGROUP_ROOT, CGROUP_SLICE)] {
+        for controller in CONTROLLERS {
+            let _ = fs::write(format!("{}/cgroup.subtree_control", parent), format!("+{}", controller));
+        }
+    }
+}
+
+// io.max only takes whole disks, so a partition is resolved to its parent device.
+fn image_block_device() -> Option<String> {
+    let dev = fs::metadata(IMAGE_PATH).ok()?.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & 0xfffff000);
+    let minor = (dev & 0xff) | ((dev >> 12) & 0xffffff00);
+
+    let sysfs = format!("/sys/dev/block/{}:{}", major, minor);
+    if Path::new(&format!("{}/partition", sysfs)).exists() {
+        return fs::read_to_string(format!("{}/../dev", sysfs)).ok().map(|device| device.trim().to_string());
+    }
+
+    Path::new(&sysfs).exists().then(|| format!("{}:{}", major, minor))
+}
+
+// Creates the workstation's cgroup under the Firebender slice and writes its limits.
+pub fn create_cgroup(workstation: &Workstation) -> Result<(), String> {
+    let vm_id = workstation.vm_id.as_str();
+    let limits = &workstation.cgroup;
+
+    if !cgroup_v2_available() {
+        error!("cgroup v2 is not mounted at {}. VM ID: {} runs without resource limits.", CGROUP_ROOT, vm_id);
+        return Ok(());
+    }
+
+    fs::create_dir_all(format!("{}/{}", CGROUP_ROOT, CGROUP_SLICE))
+        .map_err(|e| format!("Failed to create cgroup slice {}: {}", CGROUP_SLICE, e))?;
+    enable_controllers();
+
+    fs::create_dir_all(cgroup_path(vm_id))
+        .map_err(|e| format!("Failed to create cgroup for VM ID {}: {}", vm_id, e))?;
+
+    write_cgroup_file(vm_id, "cpu.weight", &limits.cpu_weight.to_string())?;
+    write_cgroup_file(vm_id, "cpu.max", &format!("{} {}", limits.cpu_quota_us, CPU_PERIOD_US))?;
+    write_cgroup_file(vm_id, "memory.max", &limits.memory_max_bytes.to_string())?;
+
+    if limits.io_max_bps.is_some() || limits.io_max_iops.is_some() {
+        let Some(device) = image_block_device() else {
+            error!("No block device found behind {}. Skipping io.max for VM ID: {}", IMAGE_PATH, vm_id);
+            return Ok(());
+        };
+
+        let bps = limits.io_max_bps.map(|bps| bps.to_string()).unwrap_or("max".to_string());
+        let iops = limits.io_max_iops.map(|iops| iops.to_string()).unwrap_or("max".to_string());
+
+        write_cgroup_file(vm_id, "io.max", &format!("{} rbps={} wbps={} riops={} wiops={}", device, bps, bps, iops, iops))?;
+    }
+
+    info!("Created cgroup {} for VM ID: {}", cgroup_path(vm_id), vm_id);
+
+    Ok(())
+}
+
+pub fn join_cgroup(vm_id: &str, pid: u32) -> Result<(), String> {
+    if !cgroup_v2_available() {
+        return Ok(());
+    }
+
+    write_cgroup_file(vm_id, "cgroup.procs", &pid.to_string())
+}
+
+pub fn set_cpu_weight(workstation: &Workstation) -> Result<(), String> {
+    if !cgroup_v2_available() {
+        return Ok(());
+    }
+
+    write_cgroup_file(&workstation.vm_id, "cpu.weight", &workstation.cgroup.cpu_weight.to_string())
+}
+
+// Removes the cgroup, first killing whatever is left in it if it is still populated.
+pub fn remove_cgroup(vm_id: &str) {
+    let path = cgroup_path(vm_id);
+    if !Path::new(&path).exists() {
+        return;
+    }
+
+    if fs::remove_dir(&path).is_ok() {
+        info!("Removed cgroup {}", path);
+        return;
+    }
+
+    let _ = fs::write(format!("{}/cgroup.kill", path), "1");
+
+    for _ in 0..REMOVE_ATTEMPTS {
+        thread::sleep(Duration::from_millis(REMOVE_RETRY_MS));
+        if fs::remove_dir(&path).is_ok() {
+            info!("Removed cgroup {}", path);
+            return;
+        }
+    }
+
+    error!("Failed to remove cgroup {}", path);
+}
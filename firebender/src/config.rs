@@ -0,0 +1,163 @@
+use std::{
+    env,
+    str::FromStr,
+};
+
+use crate::IMAGE_PATH;
+use crate::pool::{
+    PoolProfile,
+    parse_profiles,
+};
+
+use tracing::{
+    info,
+    error,
+};
+
+/*----------------------------------------------------------CONFIG----------------------------------------------------------*/
+
+pub struct Config {
+    pub max_transfer_bytes: u64,
+    pub max_exec_timeout_secs: u64,
+    pub publish_address: String,
+    pub proxy_domain: Option<String>,
+    pub ssh_gateway_address: Option<String>,
+    pub ssh_host_key_path: String,
+    pub ssh_authorized_keys: Option<String>,
+    pub cpu_overcommit_ratio: f64,
+    pub memory_overcommit_ratio: f64,
+    pub reserved_cpus: u64,
+    pub reserved_memory_mib: u64,
+    pub reserved_disk_mib: u64,
+    pub max_vcpus: u64,
+    pub min_mem_size_mib: u32,
+    pub max_mem_size_mib: u32,
+    pub max_bandwidth_mbps: u64,
+    pub max_disk_bandwidth_mbps: u64,
+    pub max_disk_iops: u64,
+    pub max_volume_size_gib: u64,
+    pub max_disk_size_gib: u64,
+    pub expiry_warning_secs: u64,
+    pub suspend_idle_secs: Option<u64>,
+    pub warm_pool: Vec<PoolProfile>,
+    pub jailer: bool,
+    pub jailer_uid_base: u32,
+    // A cgroup io.max on the whole VMM process; the per-drive limits below apply inside it, and whichever is lower wins.
+    pub io_mbps_per_vcpu: Option<u64>,
+    pub io_iops_per_vcpu: Option<u64>,
+    pub disk_bandwidth_mbps: Option<u64>,
+    pub disk_iops: Option<u64>,
+}
+
+fn env_or<T: FromStr>(var: &impl Fn(&str) -> Option<String>, name: &str, default: T) -> T {
+    match var(name) {
+        Some(value) => match value.parse::<T>() {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                error!("Invalid value for {}: {}. Using default.", name, value);
+                default
+            }
+        },
+        None => default,
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let config = Config::from_vars(|name| env::var(name).ok());
+
+        info!("File transfer size limit: {} bytes", config.max_transfer_bytes);
+        info!("Commands may run for up to {} seconds", config.max_exec_timeout_secs);
+        info!("Published ports bind to: {}", config.publish_address);
+        info!(
+            "Capacity: CPU overcommit {}x, memory overcommit {}x, reserved {} CPUs / {} MiB memory / {} MiB disk",
+            config.cpu_overcommit_ratio, config.memory_overcommit_ratio,
+            config.reserved_cpus, config.reserved_memory_mib, config.reserved_disk_mib
+        );
+        info!(
+            "Workstation limits: up to {} vCPUs, {}-{} MiB memory, up to {} Mbps bandwidth, root disks up to {} GiB, volumes up to {} GiB",
+            config.max_vcpus, config.min_mem_size_mib, config.max_mem_size_mib, config.max_bandwidth_mbps,
+            config.max_disk_size_gib, config.max_volume_size_gib
+        );
+        if let Some(secs) = config.suspend_idle_secs {
+            info!("Idle workstations are suspended after {} seconds", secs);
+        }
+        if config.io_mbps_per_vcpu.is_some() || config.io_iops_per_vcpu.is_some() {
+            info!(
+                "VMM disk I/O capped per vCPU at {} Mbps / {} IOPS",
+                config.io_mbps_per_vcpu.map_or("unlimited".to_string(), |mbps| mbps.to_string()),
+                config.io_iops_per_vcpu.map_or("unlimited".to_string(), |iops| iops.to_string())
+            );
+        }
+        if config.disk_bandwidth_mbps.is_some() || config.disk_iops.is_some() {
+            info!(
+                "Drives are rate limited by default to {} Mbps / {} IOPS",
+                config.disk_bandwidth_mbps.map_or("unlimited".to_string(), |mbps| mbps.to_string()),
+                config.disk_iops.map_or("unlimited".to_string(), |iops| iops.to_string())
+            );
+        }
+        info!(
+            "Drive rate limits may be set up to {} Mbps / {} IOPS",
+            config.max_disk_bandwidth_mbps, config.max_disk_iops
+        );
+        if config.jailer {
+            info!("VMMs run under the jailer with uids from {}", config.jailer_uid_base);
+        }
+        if let Some(domain) = &config.proxy_domain {
+            info!("Host-based proxy routing enabled for: *.{}", domain);
+        }
+
+        config
+    }
+
+    // Reads every setting through `var`, falling back to the defaults for anything it doesn't have.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        Config {
+            max_transfer_bytes: env_or(&var, "FIREBENDER_MAX_TRANSFER_BYTES", 1024 * 1024 * 1024),
+            max_exec_timeout_secs: env_or(&var, "FIREBENDER_MAX_EXEC_TIMEOUT_SECS", 3600),
+            publish_address: env_or(&var, "FIREBENDER_PUBLISH_ADDRESS", "0.0.0.0".to_string()),
+            proxy_domain: var("FIREBENDER_PROXY_DOMAIN").filter(|domain| !domain.is_empty()),
+            ssh_gateway_address: Some(env_or(&var, "FIREBENDER_SSH_GATEWAY_ADDRESS", "127.0.0.1:2222".to_string())).filter(|address| !address.is_empty()),
+            ssh_host_key_path: env_or(&var, "FIREBENDER_SSH_HOST_KEY", format!("{}ssh_host_ed25519_key", IMAGE_PATH)),
+            ssh_authorized_keys: var("FIREBENDER_SSH_AUTHORIZED_KEYS").filter(|path| !path.is_empty()),
+            cpu_overcommit_ratio: env_or(&var, "FIREBENDER_CPU_OVERCOMMIT", 4.0),
+            memory_overcommit_ratio: env_or(&var, "FIREBENDER_MEMORY_OVERCOMMIT", 1.0),
+            reserved_cpus: env_or(&var, "FIREBENDER_RESERVED_CPUS", 1),
+            reserved_memory_mib: env_or(&var, "FIREBENDER_RESERVED_MEMORY_MIB", 1024),
+            reserved_disk_mib: env_or(&var, "FIREBENDER_RESERVED_DISK_MIB", 2048),
+            max_vcpus: env_or(&var, "FIREBENDER_MAX_VCPUS", 32),
+            min_mem_size_mib: env_or(&var, "FIREBENDER_MIN_MEMORY_MIB", 128),
+            max_mem_size_mib: env_or(&var, "FIREBENDER_MAX_MEMORY_MIB", 32768),
+            max_bandwidth_mbps: env_or(&var, "FIREBENDER_MAX_BANDWIDTH_MBPS", 10000),
+            max_disk_bandwidth_mbps: env_or(&var, "FIREBENDER_MAX_DISK_BANDWIDTH_MBPS", 20000),
+            max_disk_iops: env_or(&var, "FIREBENDER_MAX_DISK_IOPS", 100000),
+            max_volume_size_gib: env_or(&var, "FIREBENDER_MAX_VOLUME_GIB", 512),
+            max_disk_size_gib: env_or(&var, "FIREBENDER_MAX_DISK_GIB", 256),
+            expiry_warning_secs: env_or(&var, "FIREBENDER_EXPIRY_WARNING_SECS", 60),
+            suspend_idle_secs: Some(env_or(&var, "FIREBENDER_SUSPEND_IDLE_SECS", 0)).filter(|secs| *secs > 0),
+            warm_pool: match parse_profiles(&var("FIREBENDER_WARM_POOL").unwrap_or_default()) {
+                Ok(profiles) => profiles,
+                Err(e) => {
+                    error!("{}. Warm pool disabled.", e);
+                    Vec::new()
+                }
+            },
+            jailer: env_or(&var, "FIREBENDER_JAILER", false),
+            jailer_uid_base: env_or(&var, "FIREBENDER_JAILER_UID_BASE", 10000),
+            io_mbps_per_vcpu: Some(env_or(&var, "FIREBENDER_IO_MBPS_PER_VCPU", 0)).filter(|mbps| *mbps > 0),
+            io_iops_per_vcpu: Some(env_or(&var, "FIREBENDER_IO_IOPS_PER_VCPU", 0)).filter(|iops| *iops > 0),
+            disk_bandwidth_mbps: Some(env_or(&var, "FIREBENDER_DISK_BANDWIDTH_MBPS", 0)).filter(|mbps| *mbps > 0),
+            disk_iops: Some(env_or(&var, "FIREBENDER_DISK_IOPS", 0)).filter(|iops| *iops > 0),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn defaults() -> Self {
+        Config::from_vars(|_| None)
+    }
+
+    // Jailed VMs each get their own uid/gid, derived from the VM's network order.
+    pub fn jail_uid(&self, order: u32) -> Option<u32> {
+        self.jailer.then(|| self.jailer_uid_base + order)
+    }
+}
@@ -0,0 +1,145 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde_json::Value;
+use std::fs;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::IMAGE_PATH;
+use crate::helpers::firecracker_api;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+// Static templates built into Firecracker for this host's architecture.
+#[cfg(target_arch = "x86_64")]
+pub const STATIC_CPU_TEMPLATES: [&str; 5] = ["C3", "T2", "T2S", "T2CL", "T2A"];
+#[cfg(target_arch = "aarch64")]
+pub const STATIC_CPU_TEMPLATES: [&str; 1] = ["V1N1"];
+
+// Top-level keys of a Firecracker custom CPU template; at least one has to be present.
+const CUSTOM_TEMPLATE_KEYS: [&str; 5] = ["kvm_capabilities", "cpuid_modifiers", "msr_modifiers", "vcpu_features", "reg_modifiers"];
+const MAX_NAME_LEN: usize = 64;
+
+pub enum CpuTemplate {
+    Static,
+    Custom(Value),
+}
+
+fn templates_dir() -> String {
+    format!("{}cpu-templates/", IMAGE_PATH)
+}
+
+fn custom_template_path(name: &str) -> String {
+    format!("{}{}.json", templates_dir(), name)
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn custom_template_names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(templates_dir()) else {
+        return Vec::new();
+    };
+
+    let mut names = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+// Resolves a template name to a built-in template or an uploaded custom one.
+pub fn find_cpu_template(name: &str) -> Option<CpuTemplate> {
+    if STATIC_CPU_TEMPLATES.contains(&name) {
+        return Some(CpuTemplate::Static);
+    }
+
+    if !valid_name(name) {
+        return None;
+    }
+
+    let template = fs::read_to_string(custom_template_path(name)).ok()?;
+    serde_json::from_str::<Value>(&template).ok().map(CpuTemplate::Custom)
+}
+
+// Applies the template before boot: static ones ride on the machine config, custom ones go to /cpu-config.
+pub fn apply_cpu_template(socket_path: &str, name: &str) -> Result<(), String> {
+    match find_cpu_template(name) {
+        Some(CpuTemplate::Static) => Ok(()),
+        Some(CpuTemplate::Custom(template)) => firecracker_api(socket_path, "PUT", "/cpu-config", &template.to_string()),
+        None => Err(format!("CPU template {} not found", name)),
+    }
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn list_cpu_templates_handler() -> impl IntoResponse {
+    (StatusCode::OK, Json(serde_json::json!({
+        "static": STATIC_CPU_TEMPLATES,
+        "custom": custom_template_names(),
+    })))
+}
+
+pub async fn get_cpu_template_handler(Path(name): Path<String>) -> impl IntoResponse {
+    match find_cpu_template(&name) {
+        Some(CpuTemplate::Custom(template)) => (StatusCode::OK, Json(template)).into_response(),
+        Some(CpuTemplate::Static) => (StatusCode::OK, Json(serde_json::json!({"static": name}))).into_response(),
+        None => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("CPU template {} not found", name)}))).into_response(),
+    }
+}
+
+pub async fn put_cpu_template_handler(
+    Path(name): Path<String>,
+    Json(template): Json<Value>,
+) -> impl IntoResponse {
+    info!("CPU template upload received: {}", name);
+
+    if !valid_name(&name) || STATIC_CPU_TEMPLATES.contains(&name.as_str()) {
+        let err_msg = format!("Invalid CPU template name {}: use up to {} letters, digits, '-' or '_', not a static template name", name, MAX_NAME_LEN);
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": err_msg}))).into_response();
+    }
+
+    let Some(fields) = template.as_object() else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "CPU template must be a JSON object"}))).into_response();
+    };
+
+    if let Some(key) = fields.keys().find(|key| !CUSTOM_TEMPLATE_KEYS.contains(&key.as_str())) {
+        let err_msg = format!("Unknown CPU template field: {}", key);
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": err_msg}))).into_response();
+    }
+
+    if fields.is_empty() {
+        let err_msg = format!("CPU template must set one of: {}", CUSTOM_TEMPLATE_KEYS.join(", "));
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": err_msg}))).into_response();
+    }
+
+    let write = fs::create_dir_all(templates_dir())
+        .and_then(|_| fs::write(custom_template_path(&name), serde_json::to_string_pretty(&template).unwrap()));
+
+    if let Err(e) = write {
+        let err_msg = format!("Failed to store CPU template {}: {}", name, e);
+        error!("{}", err_msg);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": err_msg}))).into_response();
+    }
+
+    (StatusCode::OK, Json(serde_json::json!({"status": format!("CPU template {} stored", name)}))).into_response()
+}
+
+pub async fn delete_cpu_template_handler(Path(name): Path<String>) -> impl IntoResponse {
+    info!("CPU template delete request received: {}", name);
+
+    if !valid_name(&name) || fs::remove_file(custom_template_path(&name)).is_err() {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("CPU template {} not found", name)}))).into_response();
+    }
+
+    (StatusCode::OK, Json(serde_json::json!({"status": format!("CPU template {} deleted", name)}))).into_response()
+}
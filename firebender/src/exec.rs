@@ -0,0 +1,302 @@
+use axum::{
+    extract::{ws::{WebSocket, Message}, State, Path},
+};
+use futures::{StreamExt, SinkExt};
+use regex::Regex;
+use russh::ChannelMsg;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use tracing::{info, error};
+
+use crate::{
+    AppState,
+    DEFAULT_EXEC_TIMEOUT_SECS,
+};
+use crate::config::Config;
+use crate::ssh::{
+    connect_to_workstation,
+    close_session,
+};
+use crate::suspend::ensure_running;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+#[derive(Deserialize)]
+pub struct ExecPayload {
+    pub command: String,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<String>,
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ExecStatus {
+    pub exit_code: Option<u32>,
+    pub exit_signal: Option<String>,
+    pub timed_out: bool,
+}
+
+#[derive(Serialize)]
+pub struct ExecResult {
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(flatten)]
+    pub status: ExecStatus,
+}
+
+pub enum ExecOutput {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+}
+
+/*----------------------------------------------------------EXEC----------------------------------------------------------*/
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+// Validates the request and wraps the command so it only runs once the working directory and environment are in place.
+pub fn build_command(config: &Config, payload: &ExecPayload) -> Result<String, String> {
+    if payload.command.trim().is_empty() {
+        return Err("Command must not be empty.".to_string());
+    }
+
+    if let Some(timeout_secs) = payload.timeout_secs
+        && (timeout_secs == 0 || timeout_secs > config.max_exec_timeout_secs)
+    {
+        return Err(format!("Timeout must be between 1 and {} seconds.", config.max_exec_timeout_secs));
+    }
+
+    let env_name = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+    let mut command = String::new();
+
+    if let Some(dir) = &payload.working_dir {
+        command.push_str(&format!("cd {} || exit 1; ", shell_quote(dir)));
+    }
+
+    for (key, value) in &payload.env {
+        if !env_name.is_match(key) {
+            return Err(format!("Invalid environment variable name: {}", key));
+        }
+        command.push_str(&format!("export {}={}; ", key, shell_quote(value)));
+    }
+
+    // The user's command gets its own shell, so its `;`, `&&` and `exit` cannot escape the prefix above.
+    command.push_str(&format!("exec sh -c {}", shell_quote(&payload.command)));
+
+    Ok(command)
+}
+
+pub fn exec_timeout(payload: &ExecPayload) -> Duration {
+    Duration::from_secs(payload.timeout_secs.unwrap_or(DEFAULT_EXEC_TIMEOUT_SECS))
+}
+
+pub async fn run_command(
+    ip_address: &str,
+    command: &str,
+    timeout: Duration,
+    output_tx: mpsc::UnboundedSender<ExecOutput>,
+) -> Result<ExecStatus, String> {
+
+    let session = connect_to_workstation(ip_address).await?;
+
+    let mut channel = match session.channel_open_session().await {
+        Ok(channel) => channel,
+        Err(e) => {
+            let err_msg = format!("Failed to open SSH channel on {}: {}", ip_address, e);
+            error!("{}", err_msg);
+            close_session(&session).await;
+            return Err(err_msg);
+        }
+    };
+
+    info!("Executing command on {}: {:?}", ip_address, command);
+
+    if let Err(e) = channel.exec(true, command).await {
+        let err_msg = format!("Failed to execute command on {}: {}", ip_address, e);
+        error!("{}", err_msg);
+        close_session(&session).await;
+        return Err(err_msg);
+    }
+
+    let mut exit_code = None;
+    let mut exit_signal = None;
+
+    let collect_output = async {
+        while let Some(msg) = channel.wait().await {
+            match msg {
+                ChannelMsg::Data { data } => {
+                    let _ = output_tx.send(ExecOutput::Stdout(data.to_vec()));
+                },
+                ChannelMsg::ExtendedData { data, ext: 1 } => {
+                    let _ = output_tx.send(ExecOutput::Stderr(data.to_vec()));
+                },
+                ChannelMsg::ExitStatus { exit_status } => {
+                    exit_code = Some(exit_status);
+                },
+                ChannelMsg::ExitSignal { signal_name, .. } => {
+                    exit_signal = Some(format!("{:?}", signal_name));
+                },
+                _ => {}
+            }
+        }
+    };
+
+    let timed_out = tokio::time::timeout(timeout, collect_output).await.is_err();
+
+    if timed_out {
+        error!("Command on {} timed out after {:?}", ip_address, timeout);
+    }
+
+    close_session(&session).await;
+
+    Ok(ExecStatus {
+        exit_code,
+        exit_signal,
+        timed_out,
+    })
+}
+
+pub async fn run_command_collect(ip_address: &str, command: &str, timeout: Duration) -> Result<ExecResult, String> {
+    let (output_tx, mut output_rx) = mpsc::unbounded_channel();
+
+    let status = run_command(ip_address, command, timeout, output_tx).await?;
+
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+
+    while let Ok(output) = output_rx.try_recv() {
+        match output {
+            ExecOutput::Stdout(data) => stdout.extend_from_slice(&data),
+            ExecOutput::Stderr(data) => stderr.extend_from_slice(&data),
+        }
+    }
+
+    Ok(ExecResult {
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        status,
+    })
+}
+
+/*----------------------------------------------------------WEBSOCKET----------------------------------------------------------*/
+
+fn output_frame(output: ExecOutput) -> Message {
+    let frame = match output {
+        ExecOutput::Stdout(data) => serde_json::json!({ "type": "stdout", "data": String::from_utf8_lossy(&data) }),
+        ExecOutput::Stderr(data) => serde_json::json!({ "type": "stderr", "data": String::from_utf8_lossy(&data) }),
+    };
+
+    Message::Text(frame.to_string().into())
+}
+
+fn error_frame(err: &str) -> Message {
+    Message::Text(serde_json::json!({ "type": "error", "error": err }).to_string().into())
+}
+
+pub async fn exec_ws_handler(
+    ws: WebSocket,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) {
+    info!("Exec websocket connection has been made, VM ID: {}", id);
+
+    let (mut ws_sender, mut ws_receiver) = ws.split();
+
+    if let Err(e) = ensure_running(&state, &id).await {
+        let _ = ws_sender.send(error_frame(&e)).await;
+        return;
+    }
+
+    let ip_address = {
+        let workstations = state.workstations.lock().unwrap();
+        workstations.get(&id).map(|w| w.ip_address.clone())
+    };
+
+    let ip_address = match ip_address {
+        Some(ip) => ip,
+        None => {
+            error!("Workstation not found: {}", id);
+            let _ = ws_sender.send(error_frame("VM not found")).await;
+            return;
+        }
+    };
+
+    // The first frame carries the command to run, in the same shape as the HTTP body.
+    let payload = match ws_receiver.next().await {
+        Some(Ok(Message::Text(text))) => match serde_json::from_str::<ExecPayload>(&text) {
+            Ok(payload) => payload,
+            Err(e) => {
+                let _ = ws_sender.send(error_frame(&format!("Invalid exec request: {}", e))).await;
+                return;
+            }
+        },
+        _ => {
+            info!("Exec websocket closed before a command was received");
+            return;
+        }
+    };
+
+    let command = match build_command(&state.config, &payload) {
+        Ok(command) => command,
+        Err(e) => {
+            let _ = ws_sender.send(error_frame(&e)).await;
+            return;
+        }
+    };
+    let timeout = exec_timeout(&payload);
+
+    let _session = state.activity.open_session(&id);
+
+    let (output_tx, mut output_rx) = mpsc::unbounded_channel();
+    let mut exec_task = tokio::spawn(async move {
+        run_command(&ip_address, &command, timeout, output_tx).await
+    });
+
+    let result = loop {
+        tokio::select! {
+            Some(output) = output_rx.recv() => {
+                if let Err(e) = ws_sender.send(output_frame(output)).await {
+                    error!("WebSocket data sending error: {}", e);
+                    exec_task.abort();
+                    return;
+                }
+            },
+            result = &mut exec_task => {
+                break result;
+            },
+            msg = ws_receiver.next() => {
+                if matches!(msg, None | Some(Ok(Message::Close(_))) | Some(Err(_))) {
+                    info!("Exec websocket closed by client, aborting command");
+                    exec_task.abort();
+                    return;
+                }
+            }
+        }
+    };
+
+    while let Ok(output) = output_rx.try_recv() {
+        if ws_sender.send(output_frame(output)).await.is_err() {
+            return;
+        }
+    }
+
+    let final_frame = match result {
+        Ok(Ok(status)) => Message::Text(serde_json::json!({
+            "type": "exit",
+            "exit_code": status.exit_code,
+            "exit_signal": status.exit_signal,
+            "timed_out": status.timed_out,
+        }).to_string().into()),
+        Ok(Err(e)) => error_frame(&e),
+        Err(e) => error_frame(&format!("Exec task failed: {}", e)),
+    };
+
+    let _ = ws_sender.send(final_frame).await;
+    let _ = ws_sender.close().await;
+}
@@ -0,0 +1,513 @@
+use axum::{
+    body::Body,
+    http::StatusCode,
+};
+use futures::{
+    SinkExt,
+    StreamExt,
+    channel::mpsc,
+};
+use russh_sftp::protocol::FileAttributes;
+use serde::Serialize;
+use std::io;
+use tokio::io::{
+    AsyncReadExt,
+    AsyncWriteExt,
+};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::sftp::{
+    GuestSftp,
+    sftp_error_status,
+};
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const TAR_BLOCK_BYTES: u64 = 512;
+const TAR_CHUNK_BYTES: usize = 64 * 1024;
+const TAR_CHANNEL_DEPTH: usize = 4;
+
+pub struct Download {
+    pub file_name: String,
+    pub content_type: &'static str,
+    // Unknown for directory archives, which are sent chunked.
+    pub content_length: Option<u64>,
+    pub body: Body,
+}
+
+#[derive(Serialize)]
+pub struct FsEntry {
+    pub name: String,
+    pub path: String,
+    pub kind: &'static str,
+    pub size: u64,
+    pub mode: String,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub mtime: Option<u32>,
+}
+
+/*----------------------------------------------------------FILES----------------------------------------------------------*/
+
+fn base_name(path: &str) -> String {
+    match path.trim_end_matches('/').rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => "root".to_string(),
+    }
+}
+
+enum ArchiveKind {
+    Directory,
+    File(u64),
+    Symlink(String),
+}
+
+struct ArchiveEntry {
+    remote_path: String,
+    archive_path: String,
+    mode: u32,
+    mtime: u64,
+    kind: ArchiveKind,
+}
+
+// Lists everything the archive will hold up front, so oversized or unreadable trees fail before any bytes are sent.
+async fn walk_tree(guest: &GuestSftp, root: &str, max_bytes: u64) -> Result<Vec<ArchiveEntry>, (StatusCode, String)> {
+    let mut archive = Vec::new();
+    let mut total_bytes: u64 = 0;
+    let mut pending = vec![(root.trim_end_matches('/').to_string(), base_name(root))];
+
+    while let Some((remote_dir, archive_dir)) = pending.pop() {
+        let entries = guest.sftp.read_dir(remote_dir.clone()).await
+            .map_err(|e| (sftp_error_status(&e), format!("Failed to read directory {}: {}", remote_dir, e)))?;
+
+        archive.push(ArchiveEntry {
+            remote_path: remote_dir.clone(),
+            archive_path: archive_dir.clone(),
+            mode: 0o755,
+            mtime: 0,
+            kind: ArchiveKind::Directory,
+        });
+
+        for entry in entries {
+            let metadata = entry.metadata();
+            let file_type = metadata.file_type();
+            let remote_path = format!("{}/{}", remote_dir, entry.file_name());
+            let archive_path = format!("{}/{}", archive_dir, entry.file_name());
+
+            if file_type.is_dir() {
+                pending.push((remote_path, archive_path));
+                continue;
+            }
+
+            let kind = if file_type.is_symlink() {
+                let target = guest.sftp.read_link(remote_path.clone()).await
+                    .map_err(|e| (sftp_error_status(&e), format!("Failed to read link {}: {}", remote_path, e)))?;
+                ArchiveKind::Symlink(target)
+            } else if file_type.is_file() {
+                let size = metadata.size.unwrap_or(0);
+                total_bytes += size;
+                if total_bytes > max_bytes {
+                    return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("Directory {} exceeds the transfer limit of {} bytes", root, max_bytes)));
+                }
+                ArchiveKind::File(size)
+            } else {
+                info!("Skipping special file in archive: {}", remote_path);
+                continue;
+            };
+
+            archive.push(ArchiveEntry {
+                remote_path,
+                archive_path,
+                mode: metadata.permissions.unwrap_or(0o644) & 0o7777,
+                mtime: metadata.mtime.unwrap_or(0) as u64,
+                kind,
+            });
+        }
+    }
+
+    Ok(archive)
+}
+
+// Encodes an entry's header (plus any GNU long-name records) without its data.
+fn tar_header(builder: &mut tar::Builder<Vec<u8>>, entry: &ArchiveEntry) -> io::Result<Vec<u8>> {
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(entry.mode);
+    header.set_mtime(entry.mtime);
+
+    match &entry.kind {
+        ArchiveKind::Directory => {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            header.set_cksum();
+            builder.append_data(&mut header, &entry.archive_path, io::empty())?;
+        },
+        ArchiveKind::File(size) => {
+            header.set_size(*size);
+            header.set_cksum();
+            builder.append_data(&mut header, &entry.archive_path, io::empty())?;
+        },
+        ArchiveKind::Symlink(target) => {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, &entry.archive_path, target)?;
+        },
+    }
+
+    Ok(std::mem::take(builder.get_mut()))
+}
+
+// Copies a file into the archive in chunks. The header promised `size` bytes, so a file that changed
+// in the meantime is cut off or zero-filled to match.
+async fn send_file(guest: &GuestSftp, entry: &ArchiveEntry, size: u64, tx: &mut mpsc::Sender<io::Result<Vec<u8>>>) -> io::Result<()> {
+    let disconnected = |_| io::Error::new(io::ErrorKind::BrokenPipe, "download cancelled");
+
+    let file = guest.sftp.open(entry.remote_path.clone()).await
+        .map_err(|e| io::Error::other(format!("Failed to open {}: {}", entry.remote_path, e)))?;
+    let mut file = file.take(size);
+
+    let mut sent: u64 = 0;
+    loop {
+        let mut chunk = vec![0; TAR_CHUNK_BYTES];
+        let read = file.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        chunk.truncate(read);
+        sent += read as u64;
+        tx.send(Ok(chunk)).await.map_err(disconnected)?;
+    }
+
+    let padding = (size - sent) + (TAR_BLOCK_BYTES - size % TAR_BLOCK_BYTES) % TAR_BLOCK_BYTES;
+    if padding > 0 {
+        tx.send(Ok(vec![0; padding as usize])).await.map_err(disconnected)?;
+    }
+
+    Ok(())
+}
+
+async fn send_tar(guest: &GuestSftp, entries: &[ArchiveEntry], tx: &mut mpsc::Sender<io::Result<Vec<u8>>>) -> io::Result<()> {
+    let disconnected = |_| io::Error::new(io::ErrorKind::BrokenPipe, "download cancelled");
+    let mut builder = tar::Builder::new(Vec::new());
+
+    for entry in entries {
+        let header = tar_header(&mut builder, entry)?;
+        tx.send(Ok(header)).await.map_err(disconnected)?;
+
+        if let ArchiveKind::File(size) = entry.kind {
+            send_file(guest, entry, size, tx).await?;
+        }
+    }
+
+    tx.send(builder.into_inner()).await.map_err(disconnected)
+}
+
+// Streams the archive through a channel-backed body; a failure midway aborts the response.
+fn stream_tar(guest: GuestSftp, entries: Vec<ArchiveEntry>) -> Body {
+    let (mut tx, rx) = mpsc::channel(TAR_CHANNEL_DEPTH);
+
+    tokio::spawn(async move {
+        if let Err(e) = send_tar(&guest, &entries, &mut tx).await {
+            error!("Failed to stream archive: {}", e);
+            let _ = tx.send(Err(e)).await;
+        }
+        guest.close().await;
+    });
+
+    Body::from_stream(rx)
+}
+
+pub async fn download_path(ip_address: &str, path: &str, max_bytes: u64) -> Result<Download, (StatusCode, String)> {
+    let guest = GuestSftp::open(ip_address).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    let metadata = match guest.sftp.metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            guest.close().await;
+            return Err((sftp_error_status(&e), format!("Failed to stat {}: {}", path, e)));
+        }
+    };
+
+    if metadata.file_type().is_dir() {
+        info!("Archiving directory {} on {}", path, ip_address);
+
+        let entries = match walk_tree(&guest, path, max_bytes).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                guest.close().await;
+                return Err(e);
+            }
+        };
+
+        return Ok(Download {
+            file_name: format!("{}.tar", base_name(path)),
+            content_type: "application/x-tar",
+            content_length: None,
+            body: stream_tar(guest, entries),
+        });
+    }
+
+    let size = metadata.size.unwrap_or(0);
+    if size > max_bytes {
+        guest.close().await;
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("File {} is {} bytes, exceeding the transfer limit of {} bytes", path, size, max_bytes)));
+    }
+
+    let file = match guest.sftp.open(path).await {
+        Ok(file) => file,
+        Err(e) => {
+            guest.close().await;
+            return Err((sftp_error_status(&e), format!("Failed to open {}: {}", path, e)));
+        }
+    };
+
+    info!("Streaming file {} ({} bytes) from {}", path, size, ip_address);
+
+    // The SFTP session has to outlive the file handle, so it rides along with the stream.
+    let stream = ReaderStream::new(file).map(move |chunk| {
+        let _ = &guest;
+        chunk
+    });
+
+    Ok(Download {
+        file_name: base_name(path),
+        content_type: "application/octet-stream",
+        content_length: Some(size),
+        body: Body::from_stream(stream),
+    })
+}
+
+pub async fn upload_file(
+    ip_address: &str,
+    path: &str,
+    body: Body,
+    content_length: Option<u64>,
+    max_bytes: u64,
+) -> Result<u64, (StatusCode, String)> {
+    if content_length.is_some_and(|len| len > max_bytes) {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("Upload exceeds the transfer limit of {} bytes", max_bytes)));
+    }
+
+    let guest = GuestSftp::open(ip_address).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    // The upload goes to a sibling first, so a failed transfer never touches an existing file at `path`.
+    let part_path = format!("{}.part-{}", path, Uuid::new_v4());
+
+    let mut file = match guest.sftp.create(part_path.as_str()).await {
+        Ok(file) => file,
+        Err(e) => {
+            guest.close().await;
+            return Err((sftp_error_status(&e), format!("Failed to create {}: {}", path, e)));
+        }
+    };
+
+    let mut written: u64 = 0;
+    let mut stream = body.into_data_stream();
+
+    let result = loop {
+        let chunk = match stream.next().await {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(e)) => break Err((StatusCode::BAD_REQUEST, format!("Failed to read upload body: {}", e))),
+            None => break Ok(()),
+        };
+
+        written += chunk.len() as u64;
+        if written > max_bytes {
+            break Err((StatusCode::PAYLOAD_TOO_LARGE, format!("Upload exceeds the transfer limit of {} bytes", max_bytes)));
+        }
+
+        if let Err(e) = file.write_all(&chunk).await {
+            break Err((StatusCode::BAD_GATEWAY, format!("Failed to write {}: {}", path, e)));
+        }
+    };
+
+    let result = match result {
+        Ok(()) => file.shutdown().await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Failed to write {}: {}", path, e))),
+        Err(e) => Err(e),
+    };
+    drop(file);
+
+    // Plain SFTP rename refuses to overwrite, so the old file is only removed once the new one is complete.
+    let result = match result {
+        Ok(()) => {
+            let _ = guest.sftp.remove_file(path).await;
+            guest.sftp.rename(part_path.as_str(), path).await
+                .map_err(|e| (sftp_error_status(&e), format!("Failed to move upload into place at {}: {}", path, e)))
+        },
+        Err(e) => Err(e),
+    };
+
+    if result.is_err() && guest.sftp.remove_file(part_path.as_str()).await.is_err() {
+        error!("Failed to remove partial upload: {}", part_path);
+    }
+
+    guest.close().await;
+    result?;
+
+    info!("Uploaded {} bytes to {} on {}", written, path, ip_address);
+
+    Ok(written)
+}
+
+/*----------------------------------------------------------FILESYSTEM----------------------------------------------------------*/
+
+fn fs_entry(name: String, path: String, metadata: &FileAttributes) -> FsEntry {
+    let file_type = metadata.file_type();
+
+    let kind = if file_type.is_dir() {
+        "dir"
+    } else if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    };
+
+    FsEntry {
+        name,
+        path,
+        kind,
+        size: metadata.size.unwrap_or(0),
+        mode: format!("{:04o}", metadata.permissions.unwrap_or(0) & 0o7777),
+        owner: metadata.user.clone().or(metadata.uid.map(|uid| uid.to_string())),
+        group: metadata.group.clone().or(metadata.gid.map(|gid| gid.to_string())),
+        mtime: metadata.mtime,
+    }
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+pub async fn list_dir(ip_address: &str, path: &str) -> Result<Vec<FsEntry>, (StatusCode, String)> {
+    let guest = GuestSftp::open(ip_address).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    let entries = guest.sftp.read_dir(path).await;
+    guest.close().await;
+
+    let entries = entries
+        .map_err(|e| (sftp_error_status(&e), format!("Failed to read directory {}: {}", path, e)))?;
+
+    let mut listing = entries
+        .map(|entry| fs_entry(entry.file_name(), join_path(path, &entry.file_name()), &entry.metadata()))
+        .collect::<Vec<_>>();
+
+    listing.sort_by(|a, b| (a.kind != "dir", &a.name).cmp(&(b.kind != "dir", &b.name)));
+
+    Ok(listing)
+}
+
+pub async fn stat_path(ip_address: &str, path: &str) -> Result<FsEntry, (StatusCode, String)> {
+    let guest = GuestSftp::open(ip_address).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    let metadata = guest.sftp.symlink_metadata(path).await;
+    guest.close().await;
+
+    let metadata = metadata
+        .map_err(|e| (sftp_error_status(&e), format!("Failed to stat {}: {}", path, e)))?;
+
+    Ok(fs_entry(base_name(path), path.to_string(), &metadata))
+}
+
+pub async fn make_dir(ip_address: &str, path: &str) -> Result<(), (StatusCode, String)> {
+    let guest = GuestSftp::open(ip_address).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    let result = guest.sftp.create_dir(path).await;
+    guest.close().await;
+
+    result.map_err(|e| (sftp_error_status(&e), format!("Failed to create directory {}: {}", path, e)))?;
+
+    info!("Created directory {} on {}", path, ip_address);
+
+    Ok(())
+}
+
+pub async fn rename_path(ip_address: &str, from: &str, to: &str) -> Result<(), (StatusCode, String)> {
+    let guest = GuestSftp::open(ip_address).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    let result = guest.sftp.rename(from, to).await;
+    guest.close().await;
+
+    result.map_err(|e| (sftp_error_status(&e), format!("Failed to rename {} to {}: {}", from, to, e)))?;
+
+    info!("Renamed {} to {} on {}", from, to, ip_address);
+
+    Ok(())
+}
+
+async fn remove_tree(guest: &GuestSftp, root: &str) -> Result<(), (StatusCode, String)> {
+    let mut pending = vec![root.to_string()];
+    let mut dirs = Vec::new();
+
+    while let Some(dir) = pending.pop() {
+        let entries = guest.sftp.read_dir(dir.clone()).await
+            .map_err(|e| (sftp_error_status(&e), format!("Failed to read directory {}: {}", dir, e)))?;
+
+        for entry in entries {
+            let path = join_path(&dir, &entry.file_name());
+
+            if entry.file_type().is_dir() {
+                pending.push(path);
+            } else {
+                guest.sftp.remove_file(path.clone()).await
+                    .map_err(|e| (sftp_error_status(&e), format!("Failed to delete {}: {}", path, e)))?;
+            }
+        }
+
+        dirs.push(dir);
+    }
+
+    // Children were discovered after their parents, so remove them in reverse order.
+    for dir in dirs.iter().rev() {
+        guest.sftp.remove_dir(dir.clone()).await
+            .map_err(|e| (sftp_error_status(&e), format!("Failed to delete directory {}: {}", dir, e)))?;
+    }
+
+    Ok(())
+}
+
+pub async fn delete_path(ip_address: &str, path: &str, recursive: bool) -> Result<(), (StatusCode, String)> {
+    let guest = GuestSftp::open(ip_address).await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    let metadata = match guest.sftp.symlink_metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            guest.close().await;
+            return Err((sftp_error_status(&e), format!("Failed to stat {}: {}", path, e)));
+        }
+    };
+
+    let result = if !metadata.file_type().is_dir() {
+        guest.sftp.remove_file(path).await
+            .map_err(|e| (sftp_error_status(&e), format!("Failed to delete {}: {}", path, e)))
+    } else if recursive {
+        remove_tree(&guest, path).await
+    } else {
+        guest.sftp.remove_dir(path).await
+            .map_err(|e| (sftp_error_status(&e), format!("Failed to delete directory {}: {}", path, e)))
+    };
+
+    guest.close().await;
+    result?;
+
+    info!("Deleted {} on {}", path, ip_address);
+
+    Ok(())
+}
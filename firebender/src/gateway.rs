@@ -0,0 +1,336 @@
+use russh::{
+    client,
+    keys::{
+        PrivateKey,
+        PublicKey,
+        Algorithm,
+        load_secret_key,
+        ssh_key::{LineEnding, rand_core::OsRng},
+    },
+    server::{self, Auth, Msg, Server as _, Session},
+    Channel,
+    ChannelId,
+    ChannelMsg,
+    ChannelReadHalf,
+    ChannelWriteHalf,
+    Pty,
+    Sig,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::net::TcpListener;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::AppState;
+use crate::activity::SessionGuard;
+use crate::suspend::ensure_running;
+use crate::ssh::{
+    GuestSession,
+    connect_to_workstation,
+};
+
+/*----------------------------------------------------------KEYS----------------------------------------------------------*/
+
+fn load_host_key(path: &str) -> Result<PrivateKey, String> {
+    if Path::new(path).exists() {
+        return load_secret_key(path, None)
+            .map_err(|e| format!("Failed to load SSH host key {}: {}", path, e));
+    }
+
+    info!("SSH host key {} not found. Generating a new one...", path);
+
+    let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)
+        .map_err(|e| format!("Failed to generate SSH host key: {}", e))?;
+
+    key.write_openssh_file(Path::new(path), LineEnding::LF)
+        .map_err(|e| format!("Failed to write SSH host key {}: {}", path, e))?;
+
+    Ok(key)
+}
+
+pub fn parse_public_key(key: &str) -> Result<PublicKey, String> {
+    PublicKey::from_openssh(key.trim())
+        .map_err(|e| format!("Invalid SSH public key: {}", e))
+}
+
+fn operator_keys(path: Option<&str>) -> Vec<PublicKey> {
+    let Some(path) = path else {
+        return Vec::new();
+    };
+
+    match fs::read_to_string(path) {
+        Ok(contents) => contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| parse_public_key(line).ok())
+            .collect(),
+        Err(e) => {
+            error!("Failed to read authorized keys file {}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+/*----------------------------------------------------------GATEWAY----------------------------------------------------------*/
+
+struct Gateway {
+    state: AppState,
+}
+
+impl server::Server for Gateway {
+    type Handler = GatewaySession;
+
+    fn new_client(&mut self, peer_addr: Option<SocketAddr>) -> GatewaySession {
+        info!("SSH gateway connection from {:?}", peer_addr);
+
+        GatewaySession {
+            state: self.state.clone(),
+            workstation_id: None,
+            in_use: None,
+            guest: None,
+            channels: HashMap::new(),
+        }
+    }
+}
+
+struct GatewaySession {
+    state: AppState,
+    workstation_id: Option<String>,
+    // Held for as long as the client stays connected.
+    in_use: Option<SessionGuard>,
+    guest: Option<GuestSession>,
+    channels: HashMap<ChannelId, ChannelWriteHalf<client::Msg>>,
+}
+
+impl GatewaySession {
+    fn is_authorized(&self, workstation_id: &str, public_key: &PublicKey) -> bool {
+        let workstation_keys = {
+            let workstations = self.state.workstations.lock().unwrap();
+            match workstations.get(workstation_id) {
+                Some(w) => w.ssh_public_keys.clone(),
+                None => return false,
+            }
+        };
+
+        workstation_keys
+            .iter()
+            .filter_map(|key| parse_public_key(key).ok())
+            .chain(operator_keys(self.state.config.ssh_authorized_keys.as_deref()))
+            .any(|key| key.key_data() == public_key.key_data())
+    }
+
+    async fn guest_session(&mut self) -> Result<&GuestSession, String> {
+        if self.guest.is_none() {
+            let workstation_id = self.workstation_id.clone().unwrap_or_default();
+
+            ensure_running(&self.state, &workstation_id).await?;
+
+            let ip_address = {
+                let workstations = self.state.workstations.lock().unwrap();
+                workstations.get(&workstation_id).map(|w| w.ip_address.clone())
+            };
+
+            let Some(ip_address) = ip_address else {
+                return Err(format!("Workstation not found: {}", workstation_id));
+            };
+
+            self.guest = Some(connect_to_workstation(&ip_address).await?);
+        }
+
+        Ok(self.guest.as_ref().unwrap())
+    }
+
+    fn channel(&self, channel: ChannelId) -> Result<&ChannelWriteHalf<client::Msg>, russh::Error> {
+        self.channels.get(&channel).ok_or(russh::Error::WrongChannel)
+    }
+}
+
+// Relays everything the guest sends on one channel back to the client's channel.
+async fn forward_guest_channel(mut guest: ChannelReadHalf, handle: server::Handle, id: ChannelId) {
+    while let Some(msg) = guest.wait().await {
+        let sent = match msg {
+            ChannelMsg::Data { data } => handle.data(id, data).await.is_ok(),
+            ChannelMsg::ExtendedData { data, ext } => handle.extended_data(id, ext, data).await.is_ok(),
+            ChannelMsg::ExitStatus { exit_status } => handle.exit_status_request(id, exit_status).await.is_ok(),
+            ChannelMsg::ExitSignal { signal_name, core_dumped, error_message, lang_tag } => {
+                handle.exit_signal_request(id, signal_name, core_dumped, error_message, lang_tag).await.is_ok()
+            },
+            ChannelMsg::Eof => handle.eof(id).await.is_ok(),
+            ChannelMsg::Close => break,
+            _ => true,
+        };
+
+        if !sent {
+            break;
+        }
+    }
+
+    let _ = handle.close(id).await;
+}
+
+impl server::Handler for GatewaySession {
+    type Error = russh::Error;
+
+    async fn auth_publickey_offered(&mut self, user: &str, public_key: &PublicKey) -> Result<Auth, Self::Error> {
+        if self.is_authorized(user, public_key) {
+            Ok(Auth::Accept)
+        } else {
+            Ok(Auth::reject())
+        }
+    }
+
+    async fn auth_publickey(&mut self, user: &str, public_key: &PublicKey) -> Result<Auth, Self::Error> {
+        if !self.is_authorized(user, public_key) {
+            info!("SSH gateway rejected key for workstation: {}", user);
+            return Ok(Auth::reject());
+        }
+
+        info!("SSH gateway authenticated user for workstation: {}", user);
+        self.workstation_id = Some(user.to_string());
+        self.in_use = Some(self.state.activity.open_session(user));
+
+        Ok(Auth::Accept)
+    }
+
+    async fn channel_open_session(&mut self, channel: Channel<Msg>, session: &mut Session) -> Result<bool, Self::Error> {
+        let guest = match self.guest_session().await {
+            Ok(guest) => guest,
+            Err(e) => {
+                error!("SSH gateway could not reach workstation: {}", e);
+                return Ok(false);
+            }
+        };
+
+        let guest_channel = match guest.channel_open_session().await {
+            Ok(guest_channel) => guest_channel,
+            Err(e) => {
+                error!("SSH gateway could not open guest channel: {}", e);
+                return Ok(false);
+            }
+        };
+
+        let (read_half, write_half) = guest_channel.split();
+        tokio::spawn(forward_guest_channel(read_half, session.handle(), channel.id()));
+        self.channels.insert(channel.id(), write_half);
+
+        Ok(true)
+    }
+
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        term: &str,
+        col_width: u32,
+        row_height: u32,
+        pix_width: u32,
+        pix_height: u32,
+        modes: &[(Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.channel(channel)?.request_pty(false, term, col_width, row_height, pix_width, pix_height, modes).await?;
+        session.channel_success(channel)
+    }
+
+    async fn env_request(&mut self, channel: ChannelId, variable_name: &str, variable_value: &str, session: &mut Session) -> Result<(), Self::Error> {
+        self.channel(channel)?.set_env(false, variable_name, variable_value).await?;
+        session.channel_success(channel)
+    }
+
+    async fn shell_request(&mut self, channel: ChannelId, session: &mut Session) -> Result<(), Self::Error> {
+        self.channel(channel)?.request_shell(false).await?;
+        session.channel_success(channel)
+    }
+
+    async fn exec_request(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) -> Result<(), Self::Error> {
+        self.channel(channel)?.exec(false, data).await?;
+        session.channel_success(channel)
+    }
+
+    async fn subsystem_request(&mut self, channel: ChannelId, name: &str, session: &mut Session) -> Result<(), Self::Error> {
+        self.channel(channel)?.request_subsystem(false, name).await?;
+        session.channel_success(channel)
+    }
+
+    async fn window_change_request(
+        &mut self,
+        channel: ChannelId,
+        col_width: u32,
+        row_height: u32,
+        pix_width: u32,
+        pix_height: u32,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.channel(channel)?.window_change(col_width, row_height, pix_width, pix_height).await
+    }
+
+    async fn signal(&mut self, channel: ChannelId, signal: Sig, _session: &mut Session) -> Result<(), Self::Error> {
+        self.channel(channel)?.signal(signal).await
+    }
+
+    async fn data(&mut self, channel: ChannelId, data: &[u8], _session: &mut Session) -> Result<(), Self::Error> {
+        if let Some(workstation_id) = &self.workstation_id {
+            self.state.activity.touch(workstation_id);
+        }
+        self.channel(channel)?.data(data).await
+    }
+
+    async fn channel_eof(&mut self, channel: ChannelId, _session: &mut Session) -> Result<(), Self::Error> {
+        self.channel(channel)?.eof().await
+    }
+
+    async fn channel_close(&mut self, channel: ChannelId, _session: &mut Session) -> Result<(), Self::Error> {
+        if let Some(guest_channel) = self.channels.remove(&channel) {
+            let _ = guest_channel.close().await;
+        }
+        Ok(())
+    }
+}
+
+pub async fn run_ssh_gateway(state: AppState) {
+    let Some(address) = state.config.ssh_gateway_address.clone() else {
+        info!("SSH gateway is disabled.");
+        return;
+    };
+
+    let host_key = match load_host_key(&state.config.ssh_host_key_path) {
+        Ok(key) => key,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+
+    let config = Arc::new(server::Config {
+        inactivity_timeout: Some(Duration::from_secs(3600)),
+        auth_rejection_time: Duration::from_secs(1),
+        auth_rejection_time_initial: Some(Duration::from_secs(0)),
+        keys: vec![host_key],
+        ..Default::default()
+    });
+
+    let listener = match TcpListener::bind(&address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind SSH gateway on {}: {}", address, e);
+            return;
+        }
+    };
+
+    info!("SSH gateway listening on {}", address);
+
+    let mut gateway = Gateway { state };
+    if let Err(e) = gateway.run_on_socket(config, &listener).await {
+        error!("SSH gateway stopped: {}", e);
+    }
+}
+
@@ -0,0 +1,313 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr, TcpStream},
+    process::Command,
+    thread,
+    time::Duration,
+};
+use tokio::runtime::Handle;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{
+    IMAGE_PATH,
+    Workstation,
+    WorkstationStatus,
+};
+use crate::cgroup::{
+    CgroupLimits,
+    remove_cgroup,
+};
+use crate::helpers::{
+    configure_logging,
+    configure_vm,
+    connect_vms_to_network,
+    create_bridge,
+    delete_tap,
+    firecracker_api,
+    firecracker_pid,
+    rootfs_path,
+    spawn_firecracker_process,
+    tap_name,
+};
+use crate::limits::{
+    RateLimit,
+    update_disk_limit,
+    update_network_limits,
+};
+use crate::exec::run_command_collect;
+use crate::ssh::{
+    connect_to_workstation,
+    close_session,
+};
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+// The golden VM boots with the last address of the bridge subnet; every clone wakes up with it and is re-IP'd,
+// so workstations are never given this address or any above it.
+pub const GOLDEN_ORDER: u32 = 254;
+const GOLDEN_VM_ID: &str = "golden";
+const READY_TIMEOUT_SECS: u64 = 120;
+// Re-IP checks run every 500ms, so a clone has 30 seconds to move onto its own address.
+const RE_IP_POLLS: u32 = 60;
+const RE_IP_EXEC_TIMEOUT_SECS: u64 = 15;
+
+#[derive(Serialize, Deserialize)]
+pub struct GoldenImage {
+    pub vcpu_count: u64,
+    pub mem_size_mib: u32,
+    pub ip_address: String,
+}
+
+fn golden_metadata_path() -> String {
+    format!("{}golden.json", IMAGE_PATH)
+}
+
+fn golden_state_path() -> String {
+    format!("{}golden.vmstate", IMAGE_PATH)
+}
+
+fn golden_mem_path() -> String {
+    format!("{}golden.mem", IMAGE_PATH)
+}
+
+fn golden_workstation(vcpu_count: u64, mem_size_mib: u32) -> Workstation {
+    Workstation {
+        id: GOLDEN_VM_ID.to_string(),
+        vm_id: GOLDEN_VM_ID.to_string(),
+        ip_address: format!("172.16.0.{}", GOLDEN_ORDER),
+        order: GOLDEN_ORDER,
+        vcpu_count,
+        mem_size_mib,
+        smt_enabled: false,
+        read_only: false,
+        disk_size_gib: None,
+        bandwidth: 0,
+        rx_rate_limit: RateLimit::default(),
+        tx_rate_limit: RateLimit::default(),
+        disk_rate_limit: RateLimit::default(),
+        balloon: None,
+        volumes: Vec::new(),
+        ports: Vec::new(),
+        ssh_public_keys: Vec::new(),
+        ttl: None,
+        idle_timeout: None,
+        expires_at: None,
+        status: WorkstationStatus::Running,
+        cgroup: CgroupLimits::new(vcpu_count, mem_size_mib, None),
+        cpu_affinity: None,
+        cpu_template: None,
+        jail_uid: None,
+    }
+}
+
+// Returns the captured golden image when one exists and fits the workstation's shape.
+// Jailed VMs always cold boot: the golden snapshot lives outside their chroot.
+pub fn golden_for(workstation: &Workstation) -> Option<GoldenImage> {
+    if workstation.smt_enabled
+        || workstation.read_only
+        || workstation.cpu_template.is_some()
+        || workstation.balloon.is_some()
+        || !workstation.volumes.is_empty()
+        || workstation.disk_size_gib.is_some()
+        || workstation.jail_uid.is_some()
+    {
+        return None;
+    }
+
+    let metadata = fs::read_to_string(golden_metadata_path()).ok()?;
+    let golden = serde_json::from_str::<GoldenImage>(&metadata).ok()?;
+
+    (golden.vcpu_count == workstation.vcpu_count && golden.mem_size_mib == workstation.mem_size_mib).then_some(golden)
+}
+
+/*----------------------------------------------------------CAPTURE----------------------------------------------------------*/
+
+async fn capture_golden(vcpu_count: u64, mem_size_mib: u32) -> Result<(), String> {
+    let golden = golden_workstation(vcpu_count, mem_size_mib);
+
+    create_bridge()?;
+    connect_vms_to_network(golden.order)?;
+
+    let result = boot_and_snapshot(&golden).await;
+
+    if let Some(pid) = firecracker_pid(&golden) {
+        let _ = Command::new("kill").args(["-KILL", &pid.to_string()]).status();
+    }
+    let _ = fs::remove_file(format!("{}kernel-{}.bin", IMAGE_PATH, golden.vm_id));
+    let _ = delete_tap(golden.order - 2);
+    remove_cgroup(&golden.vm_id);
+
+    result?;
+
+    let metadata = GoldenImage {
+        vcpu_count,
+        mem_size_mib,
+        ip_address: golden.ip_address.clone(),
+    };
+
+    fs::write(golden_metadata_path(), serde_json::to_string_pretty(&metadata).unwrap())
+        .map_err(|e| format!("Failed to write golden image metadata: {}", e))?;
+
+    Ok(())
+}
+
+async fn boot_and_snapshot(golden: &Workstation) -> Result<(), String> {
+    let socket_path = spawn_firecracker_process(golden)?;
+    configure_vm(&socket_path, golden)?;
+
+    info!("Waiting for golden VM to accept SSH on {}...", golden.ip_address);
+
+    let mut ready = false;
+    for _ in 0..READY_TIMEOUT_SECS {
+        if let Ok(session) = connect_to_workstation(&golden.ip_address).await {
+            close_session(&session).await;
+            ready = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    if !ready {
+        return Err("Golden VM did not become ready".to_string());
+    }
+
+    firecracker_api(&socket_path, "PATCH", "/vm", r#"{"state": "Paused"}"#)?;
+
+    let create_snapshot = format!(
+        r#"{{"snapshot_type": "Full", "snapshot_path": "{}", "mem_file_path": "{}"}}"#,
+        golden_state_path(), golden_mem_path()
+    );
+
+    firecracker_api(&socket_path, "PUT", "/snapshot/create", &create_snapshot)?;
+
+    info!("Golden snapshot written to {}", golden_state_path());
+
+    Ok(())
+}
+
+pub async fn golden_command(args: &[String]) -> Result<(), String> {
+    let usage = "Usage: firebender golden [vcpu-count] [mem-size-mib]";
+
+    let vcpu_count = match args.first() {
+        Some(value) => value.parse::<u64>().map_err(|_| format!("Invalid vCPU count: {}\n{}", value, usage))?,
+        None => 2,
+    };
+
+    let mem_size_mib = match args.get(1) {
+        Some(value) => value.parse::<u32>().map_err(|_| format!("Invalid memory size: {}\n{}", value, usage))?,
+        None => 1024,
+    };
+
+    info!("Capturing golden snapshot with {} vCPUs and {} MiB memory", vcpu_count, mem_size_mib);
+
+    capture_golden(vcpu_count, mem_size_mib).await
+}
+
+/*----------------------------------------------------------RESTORE----------------------------------------------------------*/
+
+fn guest_mac(order: u32) -> String {
+    format!("06:00:ac:10:00:{:02x}", order)
+}
+
+// Moves a freshly restored clone off the golden address onto its own IP and MAC.
+fn re_ip_guest(golden: &GoldenImage, workstation: &Workstation) -> Result<(), String> {
+    let script = format!(
+        "ip link set eth0 down && ip link set eth0 address {} && ip addr flush dev eth0 && ip addr add {}/24 dev eth0 && ip link set eth0 up && ip route replace default via 172.16.0.1",
+        guest_mac(workstation.order), workstation.ip_address
+    );
+
+    // The command cuts its own SSH connection, so it is detached inside the guest and holds off for a moment
+    // to let the exit status through first.
+    let remote_command = format!("nohup sh -c 'sleep 1; {}' >/dev/null 2>&1 &", script);
+
+    // Restores run inside `spawn_blocking`, so the SSH exec can be driven on the runtime from here.
+    let re_ip = Handle::current().block_on(run_command_collect(
+        &golden.ip_address,
+        &remote_command,
+        Duration::from_secs(RE_IP_EXEC_TIMEOUT_SECS),
+    ));
+
+    match re_ip {
+        Ok(result) if result.status.exit_code == Some(0) => {},
+        Ok(result) => return Err(format!("Failed to re-IP restored VM ID: {}: exit code {:?}", workstation.vm_id, result.status.exit_code)),
+        Err(e) => return Err(format!("Failed to re-IP restored VM ID: {}: {}", workstation.vm_id, e)),
+    }
+
+    // The script runs in the background, so wait until the clone has actually left the golden address
+    // before the next clone is allowed to come up on it.
+    for _ in 0..RE_IP_POLLS {
+        if answers_ssh(&workstation.ip_address) && !answers_ssh(&golden.ip_address) {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    Err(format!("Restored VM ID: {} did not move to {} in time", workstation.vm_id, workstation.ip_address))
+}
+
+fn answers_ssh(ip_address: &str) -> bool {
+    let Ok(ip) = ip_address.parse::<IpAddr>() else {
+        return false;
+    };
+
+    TcpStream::connect_timeout(&SocketAddr::new(ip, 22), Duration::from_millis(250)).is_ok()
+}
+
+// Brings a workstation up from the golden snapshot instead of cold booting it.
+// Callers hold `vm_counter`, so only one clone answers on the golden address at a time.
+pub fn restore_from_golden(golden: &GoldenImage, workstation: &Workstation) -> Result<(), (&'static str, String)> {
+    let vm_id = workstation.vm_id.as_str();
+
+    info!("Restoring VM ID: {} from golden snapshot", vm_id);
+
+    connect_vms_to_network(workstation.order).map_err(|e| ("network", e))?;
+
+    let golden_rootfs = rootfs_path(&golden_workstation(golden.vcpu_count, golden.mem_size_mib));
+    let clone_rootfs = rootfs_path(workstation);
+
+    let copy_fs = Command::new("cp")
+        .args(["--reflink=auto", "--sparse=always", &golden_rootfs, &clone_rootfs])
+        .status();
+
+    if copy_fs.is_err() || !copy_fs.unwrap().success() {
+        return Err(("restore", format!("Failed to clone golden root filesystem for VM ID: {}", vm_id)));
+    }
+
+    let socket_path = spawn_firecracker_process(workstation).map_err(|e| ("spawn", e))?;
+
+    let restore = || -> Result<(), String> {
+        configure_logging(&socket_path, workstation)?;
+
+        let load_snapshot = format!(
+            r#"{{"snapshot_path": "{}", "mem_backend": {{"backend_type": "File", "backend_path": "{}"}}, "resume_vm": false, "network_overrides": [{{"iface_id": "eth0", "host_dev_name": "{}"}}]}}"#,
+            golden_state_path(), golden_mem_path(), tap_name(workstation.order)
+        );
+        firecracker_api(&socket_path, "PUT", "/snapshot/load", &load_snapshot)?;
+
+        let set_fs = format!(r#"{{"drive_id": "rootfs", "path_on_host": "{}"}}"#, clone_rootfs);
+        firecracker_api(&socket_path, "PATCH", "/drives/rootfs", &set_fs)?;
+
+        if !workstation.rx_rate_limit.is_unlimited() || !workstation.tx_rate_limit.is_unlimited() {
+            update_network_limits(workstation)?;
+        }
+
+        if !workstation.disk_rate_limit.is_unlimited() {
+            update_disk_limit(workstation, workstation.disk_rate_limit)?;
+        }
+
+        firecracker_api(&socket_path, "PATCH", "/vm", r#"{"state": "Resumed"}"#)?;
+
+        re_ip_guest(golden, workstation)
+    };
+
+    restore().map_err(|e| {
+        error!("Error restoring VM from golden snapshot: {}", e);
+        ("restore", e)
+    })?;
+
+    Ok(())
+}
@@ -1,13 +1,15 @@
 use axum::{
-    extract::{Path, State, ws::WebSocketUpgrade},
+    body::Body,
+    extract::{Path, Query, State, ws::WebSocketUpgrade},
     response::{IntoResponse, Json},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode, header},
 };
 
 use crate::{
     AppState,
     CreateWorkstationPayload,
     Workstation,
+    WorkstationStatus,
 };
 
 use tracing::{
@@ -16,17 +18,93 @@ use tracing::{
 };
 
 use uuid::Uuid;
+use std::time::Instant;
+use serde::Deserialize;
 
 use crate::terminal;
+use crate::tunnel;
+use crate::exec::{
+    self,
+    ExecPayload,
+    build_command,
+    exec_timeout,
+    run_command_collect,
+};
+use crate::files::{
+    download_path,
+    upload_file,
+    list_dir,
+    stat_path,
+    make_dir,
+    rename_path,
+    delete_path,
+};
+use crate::ports::{
+    publish_port,
+    unpublish_port,
+};
+use crate::gateway::parse_public_key;
+use crate::capacity::check_capacity;
+use crate::cgroup::CgroupLimits;
+use crate::limits::RateLimit;
+use crate::pool::claim_workstation;
+use crate::suspend::ensure_running;
+use crate::validation::validate_create_payload;
+use crate::volumes::{
+    attach_volumes,
+    detach_volumes,
+};
 use crate::helpers::{
-    connect_vms_to_network,
-    spawn_firecracker_process,
-    configure_vm,
-    shutdown_vm,
+    firecracker_pid,
+    next_order,
+    provision_vm,
+    remove_workstation,
+    unix_now,
 };
 
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+#[derive(Deserialize)]
+pub struct PathQuery {
+    path: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeletePathQuery {
+    path: String,
+    #[serde(default)]
+    recursive: bool,
+}
+
+#[derive(Deserialize)]
+pub struct MkdirPayload {
+    path: String,
+}
+
+#[derive(Deserialize)]
+pub struct RenamePayload {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddSshKeyPayload {
+    public_key: String,
+}
+
+#[derive(Deserialize)]
+pub struct PublishPortPayload {
+    host_port: Option<u16>,
+    guest_port: u16,
+}
+
 /*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
 
+fn workstation_ip(state: &AppState, workstation_id: &str) -> Option<String> {
+    let workstations_map = state.workstations.lock().unwrap();
+    workstations_map.get(workstation_id).map(|w| w.ip_address.clone())
+}
+
 pub async fn root_handler() -> &'static str {
     info!("Request received at root endpoint.");
     
@@ -50,64 +128,125 @@ pub async fn create_workstation_handler(
 ) -> impl IntoResponse {
     info!("Create workstation request received.");
 
-    if payload.vcpu_count <= 0 || payload.mem_size_mib <= 0 {
-        let error_response = serde_json::json!({ "error": "Invalid vCPU or Memory values. vCPU must be > 0 and Memory must be > 0." });
+    let started = Instant::now();
+
+    if let Err(errors) = validate_create_payload(&state.config, &payload) {
+        let error_response = serde_json::json!({ "error": "Invalid workstation parameters", "fields": errors });
         return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
     }
 
+    if let Some(workstation) = claim_workstation(&state, &payload).await {
+        state.workstations.lock().unwrap().insert(workstation.id.clone(), workstation.clone());
+        state.activity.touch(&workstation.id);
+        state.metrics.observe_create(started.elapsed());
+
+        return (StatusCode::CREATED, Json(workstation)).into_response();
+    }
+
+    let _provisioning = state.metrics.track_provisioning();
+
+    // Copying and booting the VM blocks for seconds, so it runs off the async workers like the pool refill.
+    let created = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || create_workstation(&state, payload)
+    }).await;
+
+    let workstation = match created {
+        Ok(Ok(workstation)) => workstation,
+        Ok(Err((status, error_response))) => return (status, Json(error_response)).into_response(),
+        Err(e) => {
+            error!("Create task panicked: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "Failed to create workstation"}))).into_response();
+        }
+    };
+
+    state.metrics.observe_create(started.elapsed());
+
+    (StatusCode::CREATED, Json(workstation)).into_response()
+}
+
+// Admits, provisions and registers a cold-booted workstation. Holds `vm_counter` throughout, so
+// concurrent creations see each other's commitments and only one golden clone comes up at a time.
+fn create_workstation(state: &AppState, payload: CreateWorkstationPayload) -> Result<Workstation, (StatusCode, serde_json::Value)> {
     let mut vm_counter = state.vm_counter.lock().unwrap();
-    let current_vm_counter = *vm_counter;
-    *vm_counter += 1;
+
+    if let Err(shortage) = check_capacity(state, &payload) {
+        let message = shortage.message();
+        info!("Rejecting workstation creation: {}", message);
+        return Err((shortage.status, serde_json::json!({ "error": message, "shortage": shortage })));
+    }
+
+    let current_vm_counter = match next_order(&mut vm_counter) {
+        Ok(order) => order,
+        Err(e) => {
+            info!("Rejecting workstation creation: {}", e);
+            return Err((StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({ "error": e })));
+        }
+    };
 
     let vm_id = Uuid::new_v4().to_string();
 
+    // Rejections below hand the address back; nothing else can have taken one while the counter is held.
+    if let Some(affinity) = &payload.cpu_affinity
+        && let Err(taken) = state.cores.reserve(&vm_id, affinity)
+    {
+        info!("Rejecting workstation creation: cores {:?} are already pinned", taken);
+        *vm_counter = current_vm_counter;
+        let error_response = serde_json::json!({ "error": "CPU cores are already pinned by another workstation", "cores": taken });
+        return Err((StatusCode::CONFLICT, error_response));
+    }
+
+    if let Err((status, e)) = attach_volumes(state, &vm_id, &payload.volumes) {
+        info!("Rejecting workstation creation: {}", e);
+        *vm_counter = current_vm_counter;
+        state.cores.release(&vm_id);
+        return Err((status, serde_json::json!({ "error": e })));
+    }
+
     let workstation = Workstation {
         id: vm_id.clone(),
+        vm_id: vm_id.clone(),
         ip_address: format!("172.16.0.{}", current_vm_counter),
         order: current_vm_counter,
         vcpu_count: payload.vcpu_count,
         mem_size_mib: payload.mem_size_mib,
         smt_enabled: payload.smt_enabled,
         read_only: payload.read_only,
+        disk_size_gib: payload.disk_size_gib,
         bandwidth: payload.bandwidth,
+        rx_rate_limit: RateLimit::bandwidth(payload.bandwidth),
+        tx_rate_limit: RateLimit::bandwidth(payload.bandwidth),
+        disk_rate_limit: RateLimit::disk(&state.config, payload.disk_bandwidth_mbps, payload.disk_iops),
+        balloon: payload.balloon,
+        volumes: payload.volumes.iter().map(|attachment| attachment.with_rate_limit(&state.config)).collect(),
+        ports: Vec::new(),
+        ssh_public_keys: payload.ssh_public_keys.clone(),
+        ttl: payload.ttl,
+        idle_timeout: payload.idle_timeout,
+        expires_at: payload.ttl.map(|ttl| unix_now() + ttl),
+        status: WorkstationStatus::Running,
+        cgroup: CgroupLimits::for_payload(&state.config, &payload),
+        cpu_affinity: payload.cpu_affinity.clone(),
+        cpu_template: payload.cpu_template.clone(),
+        jail_uid: state.config.jail_uid(current_vm_counter),
     };
 
     info!("Creating workstation with ID: {}, IP: {}", workstation.id, workstation.ip_address);
 
-    if let Err(e) = connect_vms_to_network(current_vm_counter) {
-        error!("Error connecting VM to network: {}", e);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR, 
-            Json(serde_json::json!({"error": e}))
-        ).into_response();
-    }
-
-    let socket_path = match spawn_firecracker_process(&vm_id) {
-        Ok(path) => path,
-        Err(e) => {
-            error!("Error spawning Firecracker process: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": e })),
-            )
-                .into_response();
+    if let Err((step, e)) = provision_vm(&workstation) {
+        state.metrics.record_provisioning_failure(step);
+        // Volumes and cores stay reserved if the failed VM could not be stopped.
+        if firecracker_pid(&workstation).is_none() {
+            detach_volumes(state, &workstation.id);
+            state.cores.release(&workstation.id);
         }
-    };
-
-    if let Err(e) = configure_vm(&socket_path, &vm_id, &workstation.ip_address, current_vm_counter, 
-        payload.vcpu_count, payload.mem_size_mib, payload.smt_enabled, payload.read_only, payload.bandwidth) {
-        error!("Error configuring VM: {}", e);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({ "error": e })),
-        )
-            .into_response();
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, serde_json::json!({ "error": e })));
     }
 
-    let mut workstations_map = state.workstations.lock().unwrap();
-    workstations_map.insert(workstation.id.clone(), workstation.clone());
+    state.workstations.lock().unwrap().insert(workstation.id.clone(), workstation.clone());
+    state.activity.touch(&workstation.id);
 
-    (StatusCode::CREATED, Json(workstation)).into_response()
+    Ok(workstation)
 }
 
 pub async fn delete_workstation_handler(
@@ -116,21 +255,30 @@ pub async fn delete_workstation_handler(
 ) -> impl IntoResponse {
     info!("Delete workstation request received for ID: {}", workstation_id);
 
-    let mut workstations_map = state.workstations.lock().unwrap();
+    let started = Instant::now();
+
+    let removed = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let workstation_id = workstation_id.clone();
+        move || remove_workstation(&state, &workstation_id)
+    }).await
+        .unwrap_or_else(|e| Err(format!("Delete task panicked: {}", e)));
+
+    match removed {
+        Ok(Some(workstation)) => {
+            state.metrics.observe_delete(started.elapsed());
 
-    if let Some(workstation) = workstations_map.remove(&workstation_id) {
-        if let Err(e) = shutdown_vm(&workstation) {
+            (StatusCode::OK, Json(serde_json::json!({"status": format!("Workstation with ID {} deleted", workstation.id)}))).into_response()
+        },
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response(),
+        Err(e) => {
             error!("Error shutting down VM: {}", e);
-            return (
+            (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(serde_json::json!({ "error": e })),
             )
-                .into_response();
+                .into_response()
         }
-
-        (StatusCode::OK, Json(serde_json::json!({"status": format!("Workstation with ID {} deleted", workstation.id)}))).into_response()
-    } else {
-        (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response()
     }
 }
 
@@ -142,4 +290,290 @@ pub async fn terminal_ws_handler(
     ws.on_upgrade(move |socket| async move {
         terminal::terminal_ws_handler(socket, Path(id), State(state)).await
     })
+}
+
+pub async fn exec_workstation_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Json(payload): Json<ExecPayload>,
+) -> impl IntoResponse {
+    info!("Exec request received for ID: {}", workstation_id);
+
+    if let Err(e) = ensure_running(&state, &workstation_id).await {
+        let status = if workstation_ip(&state, &workstation_id).is_none() { StatusCode::NOT_FOUND } else { StatusCode::SERVICE_UNAVAILABLE };
+        return (status, Json(serde_json::json!({ "error": e }))).into_response();
+    }
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    let command = match build_command(&state.config, &payload) {
+        Ok(command) => command,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response(),
+    };
+
+    let session = state.activity.open_session(&workstation_id);
+    let result = run_command_collect(&ip_address, &command, exec_timeout(&payload)).await;
+    drop(session);
+
+    match result {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(e) => {
+            error!("Error executing command: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(serde_json::json!({ "error": e })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn exec_ws_handler(
+    ws: WebSocketUpgrade,
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| async move {
+        exec::exec_ws_handler(socket, Path(id), State(state)).await
+    })
+}
+
+pub async fn download_file_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Query(query): Query<PathQuery>,
+) -> impl IntoResponse {
+    info!("Download request received for ID: {}, path: {}", workstation_id, query.path);
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    match download_path(&ip_address, &query.path, state.config.max_transfer_bytes).await {
+        Ok(download) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, download.content_type.parse().unwrap());
+            if let Some(content_length) = download.content_length {
+                headers.insert(header::CONTENT_LENGTH, content_length.into());
+            }
+            if let Ok(disposition) = format!("attachment; filename=\"{}\"", download.file_name).parse() {
+                headers.insert(header::CONTENT_DISPOSITION, disposition);
+            }
+
+            (StatusCode::OK, headers, download.body).into_response()
+        },
+        Err((status, e)) => {
+            error!("Error downloading file: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn upload_file_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Query(query): Query<PathQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> impl IntoResponse {
+    info!("Upload request received for ID: {}, path: {}", workstation_id, query.path);
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    match upload_file(&ip_address, &query.path, body, content_length, state.config.max_transfer_bytes).await {
+        Ok(size) => (StatusCode::CREATED, Json(serde_json::json!({"path": query.path, "size": size}))).into_response(),
+        Err((status, e)) => {
+            error!("Error uploading file: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn list_dir_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Query(query): Query<PathQuery>,
+) -> impl IntoResponse {
+    info!("List directory request received for ID: {}, path: {}", workstation_id, query.path);
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    match list_dir(&ip_address, &query.path).await {
+        Ok(entries) => (StatusCode::OK, Json(entries)).into_response(),
+        Err((status, e)) => {
+            error!("Error listing directory: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn stat_path_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Query(query): Query<PathQuery>,
+) -> impl IntoResponse {
+    info!("Stat request received for ID: {}, path: {}", workstation_id, query.path);
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    match stat_path(&ip_address, &query.path).await {
+        Ok(entry) => (StatusCode::OK, Json(entry)).into_response(),
+        Err((status, e)) => {
+            error!("Error reading file status: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn mkdir_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Json(payload): Json<MkdirPayload>,
+) -> impl IntoResponse {
+    info!("Mkdir request received for ID: {}, path: {}", workstation_id, payload.path);
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    match make_dir(&ip_address, &payload.path).await {
+        Ok(()) => (StatusCode::CREATED, Json(serde_json::json!({"path": payload.path}))).into_response(),
+        Err((status, e)) => {
+            error!("Error creating directory: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn rename_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Json(payload): Json<RenamePayload>,
+) -> impl IntoResponse {
+    info!("Rename request received for ID: {}, {} -> {}", workstation_id, payload.from, payload.to);
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    match rename_path(&ip_address, &payload.from, &payload.to).await {
+        Ok(()) => (StatusCode::OK, Json(serde_json::json!({"from": payload.from, "to": payload.to}))).into_response(),
+        Err((status, e)) => {
+            error!("Error renaming path: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn delete_path_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Query(query): Query<DeletePathQuery>,
+) -> impl IntoResponse {
+    info!("Delete path request received for ID: {}, path: {}", workstation_id, query.path);
+
+    let Some(ip_address) = workstation_ip(&state, &workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    match delete_path(&ip_address, &query.path, query.recursive).await {
+        Ok(()) => (StatusCode::OK, Json(serde_json::json!({"status": format!("Deleted {}", query.path)}))).into_response(),
+        Err((status, e)) => {
+            error!("Error deleting path: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn list_ports_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+) -> impl IntoResponse {
+    info!("List ports request received for ID: {}", workstation_id);
+
+    let workstations_map = state.workstations.lock().unwrap();
+
+    match workstations_map.get(&workstation_id) {
+        Some(workstation) => (StatusCode::OK, Json(workstation.ports.clone())).into_response(),
+        None => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response(),
+    }
+}
+
+pub async fn publish_port_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Json(payload): Json<PublishPortPayload>,
+) -> impl IntoResponse {
+    info!("Publish port request received for ID: {}, guest port: {}", workstation_id, payload.guest_port);
+
+    match publish_port(&state, &workstation_id, payload.host_port, payload.guest_port).await {
+        Ok(mapping) => (StatusCode::CREATED, Json(mapping)).into_response(),
+        Err((status, e)) => {
+            error!("Error publishing port: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn unpublish_port_handler(
+    State(state): State<AppState>,
+    Path((workstation_id, host_port)): Path<(String, u16)>,
+) -> impl IntoResponse {
+    info!("Unpublish port request received for ID: {}, host port: {}", workstation_id, host_port);
+
+    match unpublish_port(&state, &workstation_id, host_port) {
+        Ok(()) => (StatusCode::OK, Json(serde_json::json!({"status": format!("Host port {} unpublished", host_port)}))).into_response(),
+        Err((status, e)) => {
+            error!("Error unpublishing port: {}", e);
+            (status, Json(serde_json::json!({ "error": e }))).into_response()
+        }
+    }
+}
+
+pub async fn tunnel_ws_handler(
+    ws: WebSocketUpgrade,
+    Path((id, port)): Path<(String, u16)>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| async move {
+        tunnel::tunnel_ws_handler(socket, Path((id, port)), State(state)).await
+    })
+}
+
+pub async fn add_ssh_key_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Json(payload): Json<AddSshKeyPayload>,
+) -> impl IntoResponse {
+    info!("Add SSH key request received for ID: {}", workstation_id);
+
+    if let Err(e) = parse_public_key(&payload.public_key) {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response();
+    }
+
+    let mut workstations_map = state.workstations.lock().unwrap();
+
+    match workstations_map.get_mut(&workstation_id) {
+        Some(workstation) => {
+            let public_key = payload.public_key.trim().to_string();
+            if !workstation.ssh_public_keys.contains(&public_key) {
+                workstation.ssh_public_keys.push(public_key);
+            }
+            (StatusCode::CREATED, Json(workstation.ssh_public_keys.clone())).into_response()
+        },
+        None => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response(),
+    }
 }
\ No newline at end of file
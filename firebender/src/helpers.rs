@@ -6,6 +6,42 @@ use crate::{
     ROOTFS_IMAGE_PATH,
 };
 
+use crate::ports::unpublish_all_ports;
+use crate::golden::{
+    GOLDEN_ORDER,
+    golden_for,
+    restore_from_golden,
+};
+use crate::affinity::pin_threads;
+use crate::balloon::configure_balloon;
+use crate::capacity::BYTES_PER_GIB;
+use crate::volumes::{
+    detach_volumes,
+    volume_drive_id,
+    volume_path,
+};
+use crate::cpu_template::{
+    STATIC_CPU_TEMPLATES,
+    apply_cpu_template,
+};
+use crate::cgroup::{
+    create_cgroup,
+    join_cgroup,
+    remove_cgroup,
+};
+use crate::jailer::{
+    api_path,
+    connect_jailed_vm_to_network,
+    create_vm_file,
+    delete_netns,
+    jailed_path,
+    jailer_command,
+    link_into_jail,
+    netns_name,
+    remove_jail,
+    reset_jail,
+};
+
 use tracing::{
     info,
     error,
@@ -15,12 +51,152 @@ use std::{
     process::Command,
     fs,
     thread,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 /*----------------------------------------------------------HELPERS----------------------------------------------------------*/
 
-fn delete_tap(tap_num: u32) -> Result<(), String> {
+const JAILER_START_POLLS: u32 = 40;
+// How long a VM gets to power off after Ctrl+Alt+Del, and to disappear after SIGKILL, in 50ms polls.
+const SHUTDOWN_POLLS: u32 = 100;
+const KILL_POLLS: u32 = 40;
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn vmm_log_path(workstation: &Workstation) -> String {
+    jailed_path(workstation, format!("/tmp/firecracker-{}.log", workstation.vm_id))
+}
+
+pub fn vmm_metrics_path(workstation: &Workstation) -> String {
+    jailed_path(workstation, format!("/tmp/firecracker-{}.metrics", workstation.vm_id))
+}
+
+pub fn tap_name(order: u32) -> String {
+    format!("fc-tap{}", order - 2)
+}
+
+// Hands out the next bridge address under `vm_counter`. Orders start at 2 (.1 is the bridge) and stop short of
+// the golden VM's address, which every restored clone comes up on.
+pub fn next_order(vm_counter: &mut u32) -> Result<u32, String> {
+    if *vm_counter >= GOLDEN_ORDER {
+        return Err("No free workstation addresses left on 172.16.0.0/24".to_string());
+    }
+
+    let order = *vm_counter;
+    *vm_counter += 1;
+
+    Ok(order)
+}
+
+pub fn rootfs_path(workstation: &Workstation) -> String {
+    if workstation.read_only {
+        ROOTFS_IMAGE_PATH.to_string()
+    } else {
+        format!("{}rootfs-{}.ext4", IMAGE_PATH, workstation.vm_id)
+    }
+}
+
+// Finds the Firecracker process serving a VM by the API socket on its command line.
+pub fn firecracker_pid(workstation: &Workstation) -> Option<u32> {
+    let socket_path = api_path(workstation, &firecracker_socket_path(workstation));
+
+    fs::read_dir("/proc").ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .find(|pid| {
+            fs::read(format!("/proc/{}/cmdline", pid))
+                .map(|cmdline| cmdline.split(|b| *b == 0).any(|arg| arg == socket_path.as_bytes()))
+                .unwrap_or(false)
+        })
+}
+
+fn wait_for_exit(pid: u32, polls: u32) -> bool {
+    for _ in 0..polls {
+        if !std::path::Path::new(&format!("/proc/{}", pid)).exists() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    !std::path::Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+// Stops the VM's Firecracker process outright and waits for it to go away. Succeeds if none is running.
+pub fn kill_firecracker(workstation: &Workstation) -> Result<(), String> {
+    let Some(pid) = firecracker_pid(workstation) else {
+        return Ok(());
+    };
+
+    let kill = Command::new("kill")
+        .args(["-KILL", &pid.to_string()])
+        .status();
+
+    if kill.is_err() || !kill.unwrap().success() || !wait_for_exit(pid, KILL_POLLS) {
+        return Err(format!("Failed to stop Firecracker process for VM ID: {}", workstation.vm_id));
+    }
+
+    let _ = fs::remove_file(firecracker_socket_path(workstation));
+
+    Ok(())
+}
+
+pub fn firecracker_socket_path(workstation: &Workstation) -> String {
+    jailed_path(workstation, format!("/tmp/firecracker-{}.socket", workstation.vm_id))
+}
+
+pub fn snapshot_state_path(workstation: &Workstation) -> String {
+    jailed_path(workstation, format!("{}snapshot-{}.vmstate", IMAGE_PATH, workstation.vm_id))
+}
+
+pub fn snapshot_mem_path(workstation: &Workstation) -> String {
+    jailed_path(workstation, format!("{}snapshot-{}.mem", IMAGE_PATH, workstation.vm_id))
+}
+
+// Sends one request to the Firecracker API and fails on any non-2xx answer.
+pub fn firecracker_api(socket_path: &str, method: &str, endpoint: &str, body: &str) -> Result<(), String> {
+    let url = format!("http://localhost{}", endpoint);
+
+    let output = Command::new("curl")
+        .args(["-s", "--fail-with-body", "--unix-socket", socket_path, "-X", method, &url, "-d", body, "-H", "Content-Type: application/json"])
+        .output()
+        .map_err(|e| format!("Failed to call Firecracker API {} {}: {}", method, endpoint, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Firecracker API {} {} failed: {}",
+            method, endpoint, String::from_utf8_lossy(&output.stdout).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+// Reads a JSON resource from the Firecracker API.
+pub fn firecracker_get(socket_path: &str, endpoint: &str) -> Result<serde_json::Value, String> {
+    let url = format!("http://localhost{}", endpoint);
+
+    let output = Command::new("curl")
+        .args(["-s", "--fail-with-body", "--unix-socket", socket_path, &url])
+        .output()
+        .map_err(|e| format!("Failed to call Firecracker API GET {}: {}", endpoint, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Firecracker API GET {} failed: {}",
+            endpoint, String::from_utf8_lossy(&output.stdout).trim()
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Invalid response from Firecracker API GET {}: {}", endpoint, e))
+}
+
+pub fn delete_tap(tap_num: u32) -> Result<(), String> {
     let tap = format!("fc-tap{}", tap_num);
     info!("Deleting tap device: {}", &tap);
     let delete_tap = Command::new("sudo")
@@ -39,20 +215,27 @@ fn delete_tap(tap_num: u32) -> Result<(), String> {
 
 pub fn cleanup_taps(app_state: &AppState) -> Result<(), String> {
     let vm_count = app_state.vm_counter.lock().unwrap();
-    let workstations = app_state.workstations.lock().unwrap();
+    let mut workstations = app_state.workstations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+    workstations.extend(app_state.warm_pool.workstations());
 
     for i in 2..*vm_count {
-        if let Err(e) = delete_tap(i - 2) {
-            return Err(e);
+        let workstation = workstations.iter().find(|w| w.order == i);
+
+        if workstation.is_some_and(|w| w.jail_uid.is_some()) {
+            delete_netns(i)?;
+        } else {
+            delete_tap(i - 2)?;
         }
 
-        if let Some(workstation) = workstations.values().find(|w| w.order == i) {
+        if let Some(workstation) = workstation {
+            remove_jail(&workstation.vm_id);
+
             if workstation.read_only {
                 info!("VM ID: {} is read-only. Skipping disk cleanup.", workstation.id);
             }
             else {
-                let kernel = format!("{}kernel-{}.bin", IMAGE_PATH, workstation.id);
-                let fs = format!("{}rootfs-{}.ext4", IMAGE_PATH, workstation.id);
+                let kernel = format!("{}kernel-{}.bin", IMAGE_PATH, workstation.vm_id);
+                let fs = format!("{}rootfs-{}.ext4", IMAGE_PATH, workstation.vm_id);
                 
                 let delete_kernel = fs::remove_file(&kernel); 
                 if delete_kernel.is_err() {
@@ -159,20 +342,33 @@ pub fn connect_vms_to_network(vm_count: u32) -> Result<(), String> {
     Ok(())
 }
 
-pub fn spawn_firecracker_process(vm_id: &str) -> Result<String, String> {
-    let socket_path = format!("/tmp/firecracker-{}.socket", vm_id);
+pub fn spawn_firecracker_process(workstation: &Workstation) -> Result<String, String> {
+    let socket_path = firecracker_socket_path(workstation);
 
     let _ = fs::remove_file(&socket_path);
 
-    let vm_id = vm_id.to_string();
+    create_cgroup(workstation)?;
+
+    let mut command = match workstation.jail_uid {
+        Some(uid) => {
+            reset_jail(&workstation.vm_id);
+            jailer_command(workstation, uid, &socket_path)
+        },
+        None => {
+            let mut command = Command::new("firecracker");
+            command.arg("--api-sock").arg(&socket_path);
+            command
+        }
+    };
+
+    let vm_id = workstation.vm_id.clone();
+    let jailed = workstation.jail_uid.is_some();
     let socket_path_thread = socket_path.clone();
 
     thread::spawn(move || {
         info!("Spawning Firecracker process with socket: {}", &socket_path_thread);
         
-        let mut start_firecracker = match Command::new("firecracker")
-            .arg("--api-sock")
-            .arg(&socket_path_thread)
+        let mut start_firecracker = match command
             .stdin(std::process::Stdio::null())
             .stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::null())
@@ -184,8 +380,12 @@ pub fn spawn_firecracker_process(vm_id: &str) -> Result<String, String> {
                     return;
                 }
             };
-        
-        
+
+        // The jailer joins the cgroup itself; a plain Firecracker process is moved in right after spawning.
+        if !jailed && let Err(e) = join_cgroup(&vm_id, start_firecracker.id()) {
+            error!("{}", e);
+        }
+
         match start_firecracker.wait() {
             Ok(status) => {
                 if status.success() {
@@ -207,21 +407,140 @@ pub fn spawn_firecracker_process(vm_id: &str) -> Result<String, String> {
 
     thread::sleep(Duration::from_millis(50));
 
+    // The jailer sets up the chroot, cgroups and namespace before Firecracker opens its socket.
+    if workstation.jail_uid.is_some() {
+        for _ in 0..JAILER_START_POLLS {
+            if std::path::Path::new(&socket_path).exists() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
     Ok(socket_path)
 }
 
-pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_counter: u32, 
-    vcpu_count: u64, mem_size_mib: u32, smt_enabled: bool, read_only: bool, bandwidth: u64) -> Result<(), String> {
-    
-    let set_machine_cfg = format!(
-        r#"{{"vcpu_count": {}, "mem_size_mib": {}, "smt": {}}}"#,
-        vcpu_count, mem_size_mib, smt_enabled
+// Points the Firecracker logger and metrics at per-VM files; must happen before boot or snapshot load.
+pub fn configure_logging(socket_path: &str, workstation: &Workstation) -> Result<(), String> {
+    let vm_id = workstation.vm_id.as_str();
+    let log_path = vmm_log_path(workstation);
+    let metrics_path = vmm_metrics_path(workstation);
+
+    if create_vm_file(workstation, &log_path).is_err() || create_vm_file(workstation, &metrics_path).is_err() {
+        let err_msg = format!("Failed to create log and metrics files for VM ID: {}", vm_id);
+        error!("{}", err_msg);
+        return Err(err_msg);
+    }
+
+    let set_logger = format!(
+        r#"{{"log_path": "{}", "level": "Info", "show_level": true, "show_log_origin": false}}"#,
+        api_path(workstation, &log_path)
     );
 
+    let configure_logger = Command::new("curl")
+        .args(["--unix-socket", socket_path, "-X", "PUT", "http://localhost/logger", "-d", &set_logger, "-H", "Content-Type: application/json"])
+        .status();
+
+    if configure_logger.is_err() || !configure_logger.unwrap().success() {
+        let err_msg = format!("Failed to configure logger for VM ID: {}", vm_id);
+        error!("{}", err_msg);
+        return Err(err_msg);
+    }
+
+    let set_metrics = format!(r#"{{"metrics_path": "{}"}}"#, api_path(workstation, &metrics_path));
+
+    let configure_metrics = Command::new("curl")
+        .args(["--unix-socket", socket_path, "-X", "PUT", "http://localhost/metrics", "-d", &set_metrics, "-H", "Content-Type: application/json"])
+        .status();
+
+    if configure_metrics.is_err() || !configure_metrics.unwrap().success() {
+        let err_msg = format!("Failed to configure metrics for VM ID: {}", vm_id);
+        error!("{}", err_msg);
+        return Err(err_msg);
+    }
+
+    Ok(())
+}
+
+// Sparsely extends a per-VM rootfs copy and grows its ext4 filesystem to fill it, before the guest mounts it.
+fn grow_rootfs(path: &str, disk_size_gib: u64) -> Result<(), String> {
+    info!("Growing root filesystem {} to {} GiB", path, disk_size_gib);
+
+    let extend = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_len(disk_size_gib * BYTES_PER_GIB));
+
+    if let Err(e) = extend {
+        let err_msg = format!("Failed to extend root filesystem {}: {}", path, e);
+        error!("{}", err_msg);
+        return Err(err_msg);
+    }
+
+    // resize2fs insists on a freshly checked filesystem; exit codes 0 and 1 mean it is clean or was repaired.
+    let check = Command::new("e2fsck")
+        .args(["-fy", path])
+        .output();
+
+    match check {
+        Ok(output) if matches!(output.status.code(), Some(0 | 1)) => {},
+        Ok(output) => {
+            let err_msg = format!("Failed to check root filesystem {}: {}", path, String::from_utf8_lossy(&output.stdout).trim());
+            error!("{}", err_msg);
+            return Err(err_msg);
+        },
+        Err(e) => {
+            let err_msg = format!("Failed to run e2fsck on {}: {}", path, e);
+            error!("{}", err_msg);
+            return Err(err_msg);
+        }
+    }
+
+    let resize = Command::new("resize2fs")
+        .arg(path)
+        .output();
+
+    match resize {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => {
+            let err_msg = format!("Failed to resize root filesystem {}: {}", path, String::from_utf8_lossy(&output.stderr).trim());
+            error!("{}", err_msg);
+            Err(err_msg)
+        },
+        Err(e) => {
+            let err_msg = format!("Failed to run resize2fs on {}: {}", path, e);
+            error!("{}", err_msg);
+            Err(err_msg)
+        }
+    }
+}
+
+pub fn configure_vm(socket_path: &str, workstation: &Workstation) -> Result<(), String> {
+    let vm_id = workstation.vm_id.as_str();
+    let ip_addr = workstation.ip_address.as_str();
+    let current_vm_counter = workstation.order;
+    let vcpu_count = workstation.vcpu_count;
+    let mem_size_mib = workstation.mem_size_mib;
+    let smt_enabled = workstation.smt_enabled;
+    let read_only = workstation.read_only;
+
+    let static_template = workstation.cpu_template.as_deref().filter(|name| STATIC_CPU_TEMPLATES.contains(name));
+
+    let set_machine_cfg = match static_template {
+        Some(template) => format!(
+            r#"{{"vcpu_count": {}, "mem_size_mib": {}, "smt": {}, "cpu_template": "{}"}}"#,
+            vcpu_count, mem_size_mib, smt_enabled, template
+        ),
+        None => format!(
+            r#"{{"vcpu_count": {}, "mem_size_mib": {}, "smt": {}}}"#,
+            vcpu_count, mem_size_mib, smt_enabled
+        ),
+    };
+
     info!("Configuring VM Kernel ID: {} with {} vCPUs, {} MiB RAM, SMT: {}, IP: {}", vm_id, vcpu_count, mem_size_mib, smt_enabled, ip_addr);
 
     let configure_machine = Command::new("curl")
-        .args(["--unix-socket", &socket_path, "-X", "PUT", "http://localhost/machine-config", "-d", &set_machine_cfg, "-H", "Content-Type: application/json"])
+        .args(["--unix-socket", socket_path, "-X", "PUT", "http://localhost/machine-config", "-d", &set_machine_cfg, "-H", "Content-Type: application/json"])
         .status();
 
     if configure_machine.is_err() || !configure_machine.unwrap().success() {
@@ -230,13 +549,19 @@ pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_co
         return Err(err_msg);
     }
 
+    if let Some(template) = &workstation.cpu_template {
+        apply_cpu_template(socket_path, template)?;
+    }
+
+    configure_logging(socket_path, workstation)?;
+
     let boot_args = format!(
         "console=ttyS0 reboot=k panic=1 pci=off ip={}::172.16.0.1:255.255.255.0::eth0:on i8042.noaux i8042.nomux i8042.nopnp i8042.dumbkbd",
         ip_addr
     );
 
     let kernel_path = if read_only {
-        format!("{}", KERNEL_IMAGE_PATH)
+        KERNEL_IMAGE_PATH.to_string()
     } else {
         let customized_kernel = format!("{}kernel-{}.bin", IMAGE_PATH, vm_id);
 
@@ -251,13 +576,15 @@ pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_co
         customized_kernel
     };
 
+    let kernel_path = link_into_jail(workstation, &kernel_path, !read_only)?;
+
     let set_kernel = format!(
         r#"{{"kernel_image_path": "{}", "boot_args": "{}"}}"#,
         kernel_path, boot_args
     );
 
     let configure_kernel = Command::new("curl")
-        .args(["--unix-socket", &socket_path, "-X", "PUT", "http://localhost/boot-source", "-d", &set_kernel, "-H", "Content-Type: application/json"])
+        .args(["--unix-socket", socket_path, "-X", "PUT", "http://localhost/boot-source", "-d", &set_kernel, "-H", "Content-Type: application/json"])
         .status();
     
     if configure_kernel.is_err() || !configure_kernel.unwrap().success() {
@@ -267,9 +594,9 @@ pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_co
     }
 
     let rootfs_path = if read_only {
-        format!("{}", ROOTFS_IMAGE_PATH)
+        ROOTFS_IMAGE_PATH.to_string()
     } else {
-        let customized_rootfs = format!("{}rootfs-{}.ext4", IMAGE_PATH, vm_id);
+        let customized_rootfs = rootfs_path(workstation);
 
         let copy_fs = fs::copy(ROOTFS_IMAGE_PATH, &customized_rootfs);
 
@@ -279,18 +606,31 @@ pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_co
             return Err(err_msg);
         }
 
+        if let Some(disk_size_gib) = workstation.disk_size_gib {
+            grow_rootfs(&customized_rootfs, disk_size_gib)?;
+        }
+
         customized_rootfs
     };
 
-    let set_fs = format!(
-        r#"{{"drive_id": "rootfs", "path_on_host": "{}", "is_root_device": true, "is_read_only": {}}}"#,
-        rootfs_path, read_only
-    );
+    let rootfs_path = link_into_jail(workstation, &rootfs_path, !read_only)?;
+
+    let mut set_fs = serde_json::json!({
+        "drive_id": "rootfs",
+        "path_on_host": rootfs_path,
+        "is_root_device": true,
+        "is_read_only": read_only,
+    });
+
+    if !workstation.disk_rate_limit.is_unlimited() {
+        set_fs["rate_limiter"] = workstation.disk_rate_limit.to_firecracker();
+    }
+    let set_fs = set_fs.to_string();
 
     info!("Configuring VM RootFS ID: {} as Read-Only: {}", vm_id, read_only);
 
     let configure_fs = Command::new("curl")
-        .args(["--unix-socket", &socket_path, "-X", "PUT", "http://localhost/drives/rootfs", "-d", &set_fs, "-H", "Content-Type: application/json"])
+        .args(["--unix-socket", socket_path, "-X", "PUT", "http://localhost/drives/rootfs", "-d", &set_fs, "-H", "Content-Type: application/json"])
         .status();
     
     if configure_fs.is_err() || !configure_fs.unwrap().success() {
@@ -299,26 +639,43 @@ pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_co
         return Err(err_msg);
     }
 
-    let configure_network = if bandwidth > 0 {
+    for (i, attachment) in workstation.volumes.iter().enumerate() {
+        let drive_id = volume_drive_id(i);
+        let volume_path = link_into_jail(workstation, &volume_path(&attachment.id), !attachment.read_only)?;
 
-        let bps = bandwidth * 125000; // Mbits to Bytes
+        let mut set_volume = serde_json::json!({
+            "drive_id": drive_id,
+            "path_on_host": volume_path,
+            "is_root_device": false,
+            "is_read_only": attachment.read_only,
+        });
 
-        format!(
-            r#"{{"iface_id": "eth0", "host_dev_name": "fc-tap{}", "tx_rate_limiter": {{"bandwidth": {{"size": {}, "refill_time": 1000}}}}, "rx_rate_limiter": {{"bandwidth": {{"size": {}, "refill_time": 1000}}}}}}"#,
-            current_vm_counter - 2,
-            bps,
-            bps
-        )
-        
-    } else {
-        format!(
-            r#"{{"iface_id": "eth0", "host_dev_name": "fc-tap{}"}}"#,
-            current_vm_counter - 2
-        )
-    };
+        if !attachment.rate_limit.is_unlimited() {
+            set_volume["rate_limiter"] = attachment.rate_limit.to_firecracker();
+        }
+
+        info!("Attaching volume {} to VM ID: {} as {}", attachment.id, vm_id, drive_id);
+
+        if let Err(e) = firecracker_api(socket_path, "PUT", &format!("/drives/{}", drive_id), &set_volume.to_string()) {
+            let err_msg = format!("Failed to attach volume {} to VM ID: {}: {}", attachment.id, vm_id, e);
+            error!("{}", err_msg);
+            return Err(err_msg);
+        }
+    }
+
+    let mut configure_network = serde_json::json!({
+        "iface_id": "eth0",
+        "host_dev_name": format!("fc-tap{}", current_vm_counter - 2),
+    });
+
+    if !workstation.rx_rate_limit.is_unlimited() || !workstation.tx_rate_limit.is_unlimited() {
+        configure_network["rx_rate_limiter"] = workstation.rx_rate_limit.to_firecracker();
+        configure_network["tx_rate_limiter"] = workstation.tx_rate_limit.to_firecracker();
+    }
+    let configure_network = configure_network.to_string();
 
     let set_network = Command::new("curl")
-        .args(["--unix-socket", &socket_path, "-X", "PUT", "http://localhost/network-interfaces/eth0", "-d", &configure_network, "-H", "Content-Type: application/json"])
+        .args(["--unix-socket", socket_path, "-X", "PUT", "http://localhost/network-interfaces/eth0", "-d", &configure_network, "-H", "Content-Type: application/json"])
         .status();
     
     if set_network.is_err() || !set_network.unwrap().success() {
@@ -327,8 +684,13 @@ pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_co
         return Err(err_msg);
     }
 
+    if let Some(balloon) = &workstation.balloon {
+        info!("Configuring VM ID: {} with a {} MiB balloon", vm_id, balloon.amount_mib);
+        configure_balloon(socket_path, balloon)?;
+    }
+
     let start_vm = Command::new("curl")
-        .args(["--unix-socket", &socket_path, "-X", "PUT", "http://localhost/actions", "-d", r#"{"action_type": "InstanceStart"}"#, "-H", "Content-Type: application/json"])
+        .args(["--unix-socket", socket_path, "-X", "PUT", "http://localhost/actions", "-d", r#"{"action_type": "InstanceStart"}"#, "-H", "Content-Type: application/json"])
         .status();
     
     if start_vm.is_err() || !start_vm.unwrap().success() {
@@ -340,49 +702,144 @@ pub fn configure_vm(socket_path: &str, vm_id: &str, ip_addr: &str, current_vm_co
     Ok(())
 }
 
+// Creates the TAP device, starts Firecracker and boots the VM (or restores it from the golden snapshot),
+// then pins its threads; failures name the step that broke and leave nothing running.
+pub fn provision_vm(workstation: &Workstation) -> Result<(), (&'static str, String)> {
+    let provisioned = match golden_for(workstation) {
+        Some(golden) => restore_from_golden(&golden, workstation),
+        None => boot_vm(workstation),
+    }.and_then(|()| pin_threads(workstation).map_err(|e| {
+        error!("Error pinning VM threads: {}", e);
+        ("pin", e)
+    }));
+
+    // Whatever the failed step left behind (TAP, process, cgroup, disk copies) is torn down here, so callers
+    // only have to give back what they reserved.
+    if provisioned.is_err() && let Err(e) = shutdown_vm(workstation) {
+        error!("Failed to clean up VM ID: {} after provisioning failed: {}", workstation.vm_id, e);
+    }
+
+    provisioned
+}
+
+fn boot_vm(workstation: &Workstation) -> Result<(), (&'static str, String)> {
+    let connect = match workstation.jail_uid {
+        Some(uid) => connect_jailed_vm_to_network(workstation, uid),
+        None => connect_vms_to_network(workstation.order),
+    };
+
+    if let Err(e) = connect {
+        error!("Error connecting VM to network: {}", e);
+        return Err(("network", e));
+    }
+
+    let socket_path = match spawn_firecracker_process(workstation) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("Error spawning Firecracker process: {}", e);
+            return Err(("spawn", e));
+        }
+    };
+
+    if let Err(e) = configure_vm(&socket_path, workstation) {
+        error!("Error configuring VM: {}", e);
+        return Err(("configure", e));
+    }
+
+    Ok(())
+}
+
 pub fn shutdown_vm(workstation: &Workstation) -> Result<(), String> {
     info!("Shutting down VM ID: {}", workstation.id);
 
-    let socket_path = format!("/tmp/firecracker-{}.socket", workstation.id);
+    let socket_path = firecracker_socket_path(workstation);
 
-    let shutdown_vm = Command::new("curl")
-        .args(["--unix-socket", &socket_path, "-X", "PUT", "http://localhost/actions", "-d", r#"{"action_type": "SendCtrlAltDel"}"#, "-H", "Content-Type: application/json"])
-        .status();
+    // Give the guest a chance to power off cleanly; whatever is still running afterwards is killed.
+    if let Some(pid) = firecracker_pid(workstation) {
+        let shutdown_vm = Command::new("curl")
+            .args(["--unix-socket", &socket_path, "-X", "PUT", "http://localhost/actions", "-d", r#"{"action_type": "SendCtrlAltDel"}"#, "-H", "Content-Type: application/json"])
+            .status();
 
-    if std::path::Path::new(&socket_path).exists() {
         if shutdown_vm.is_err() || !shutdown_vm.unwrap().success() {
-            let err_msg = format!("Failed to send shutdown signal to VM ID: {}", workstation.id);
-            error!("{}", err_msg);
-            return Err(err_msg);    
+            error!("Failed to send shutdown signal to VM ID: {}. Killing it.", workstation.id);
+        } else {
+            wait_for_exit(pid, SHUTDOWN_POLLS);
         }
     }
 
+    if let Err(e) = kill_firecracker(workstation) {
+        error!("{}", e);
+        return Err(e);
+    }
+
     if workstation.read_only {
         info!("VM ID: {} is read-only. Skipping disk cleanup.", workstation.id);
     }
     else {
-        let kernel = format!("{}kernel-{}.bin", IMAGE_PATH, workstation.id);
-        let fs = format!("{}rootfs-{}.ext4", IMAGE_PATH, workstation.id);
+        let kernel = format!("{}kernel-{}.bin", IMAGE_PATH, workstation.vm_id);
+        let fs = format!("{}rootfs-{}.ext4", IMAGE_PATH, workstation.vm_id);
         
         let delete_kernel = fs::remove_file(&kernel); 
-        if delete_kernel.is_err() {
+        if delete_kernel.is_err_and(|e| e.kind() != std::io::ErrorKind::NotFound) {
             let err_msg = format!("Failed to delete kernel image for VM ID: {}", workstation.id);
             error!("{}", err_msg);
         }
 
         let delete_fs = fs::remove_file(&fs);
-        if delete_fs.is_err() {
+        if delete_fs.is_err_and(|e| e.kind() != std::io::ErrorKind::NotFound) {
             let err_msg = format!("Failed to delete root filesystem for VM ID: {}", workstation.id);
             error!("{}", err_msg);
         }
     }
 
-    thread::sleep(Duration::from_secs(5));
+    let _ = fs::remove_file(vmm_log_path(workstation));
+    let _ = fs::remove_file(vmm_metrics_path(workstation));
+    let _ = fs::remove_file(snapshot_state_path(workstation));
+    let _ = fs::remove_file(snapshot_mem_path(workstation));
+
+    remove_cgroup(&workstation.vm_id);
+
+    // A VM that failed early may never have had its TAP device or namespace created.
+    let delete_network = if workstation.jail_uid.is_some() {
+        remove_jail(&workstation.vm_id);
+        if std::path::Path::new(&format!("/var/run/netns/{}", netns_name(workstation.order))).exists() {
+            delete_netns(workstation.order)
+        } else {
+            Ok(())
+        }
+    } else if std::path::Path::new(&format!("/sys/class/net/{}", tap_name(workstation.order))).exists() {
+        delete_tap(workstation.order - 2)
+    } else {
+        Ok(())
+    };
 
-    if let Err(e) = delete_tap(workstation.order - 2) {
+    if let Err(e) = delete_network {
         error!("{}", e);
         return Err(e);
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Takes a workstation out of the registry and tears down everything that belongs to it. Blocks for the
+// length of the shutdown, so async callers run it in `spawn_blocking`; the registry lock is only held for the removal.
+pub fn remove_workstation(state: &AppState, workstation_id: &str) -> Result<Option<Workstation>, String> {
+    let Some(workstation) = state.workstations.lock().unwrap().remove(workstation_id) else {
+        return Ok(None);
+    };
+
+    unpublish_all_ports(state, &workstation.id);
+    state.activity.forget(&workstation.id);
+    state.update_locks.lock().unwrap().remove(&workstation.id);
+
+    // Pinned cores and volumes only go back to the pool once the VM's threads and open images are gone. A
+    // Firecracker process that survived the kill keeps both, so nothing else gets handed a disk it still writes to.
+    let shutdown = shutdown_vm(&workstation);
+    if firecracker_pid(&workstation).is_none() {
+        detach_volumes(state, &workstation.id);
+        state.cores.release(&workstation.id);
+    }
+    shutdown?;
+
+    Ok(Some(workstation))
+}
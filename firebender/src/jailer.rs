@@ -0,0 +1,189 @@
+use std::{
+    fs,
+    os::unix::fs::chown,
+    path::Path,
+    process::Command,
+};
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{
+    Workstation,
+    FIRECRACKER_PATH,
+    JAILER_CHROOT_BASE,
+    JAILER_PATH,
+};
+use crate::cgroup::CGROUP_SLICE;
+
+/*----------------------------------------------------------PATHS----------------------------------------------------------*/
+
+// Everything the jailer creates for a VM lives under this directory.
+pub fn jail_dir(vm_id: &str) -> String {
+    format!("{}/firecracker/{}", JAILER_CHROOT_BASE, vm_id)
+}
+
+pub fn jail_root(vm_id: &str) -> String {
+    format!("{}/root", jail_dir(vm_id))
+}
+
+// Where a per-VM file lives on the host: inside the chroot for jailed VMs, unchanged otherwise.
+pub fn jailed_path(workstation: &Workstation, path: String) -> String {
+    if workstation.jail_uid.is_none() {
+        return path;
+    }
+
+    let file_name = Path::new(&path).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    format!("{}/{}", jail_root(&workstation.vm_id), file_name)
+}
+
+// The same file as Firecracker sees it from inside its chroot.
+pub fn api_path(workstation: &Workstation, host_path: &str) -> String {
+    let jail_root = jail_root(&workstation.vm_id);
+
+    match host_path.strip_prefix(&jail_root) {
+        Some(path) if workstation.jail_uid.is_some() => path.to_string(),
+        _ => host_path.to_string(),
+    }
+}
+
+pub fn netns_name(order: u32) -> String {
+    format!("fc-ns{}", order - 2)
+}
+
+// Host end of a jailed VM's veth pair; it carries the same traffic the host-side TAP would.
+pub fn veth_name(order: u32) -> String {
+    format!("fc-veth{}", order - 2)
+}
+
+/*----------------------------------------------------------FILES----------------------------------------------------------*/
+
+// Creates an empty file the jailed VMM writes to (logs, metrics, snapshots) and hands it to the VM's uid.
+pub fn create_vm_file(workstation: &Workstation, host_path: &str) -> Result<(), String> {
+    fs::File::create(host_path)
+        .map_err(|e| format!("Failed to create {} for VM ID {}: {}", host_path, workstation.vm_id, e))?;
+
+    if let Some(uid) = workstation.jail_uid {
+        chown(host_path, Some(uid), Some(uid))
+            .map_err(|e| format!("Failed to chown {} for VM ID {}: {}", host_path, workstation.vm_id, e))?;
+    }
+
+    Ok(())
+}
+
+// Hard-links an image into the jail and returns its in-jail path. Shared base images are linked as-is;
+// per-VM copies are handed to the VM's uid so the guest can write to them.
+pub fn link_into_jail(workstation: &Workstation, path: &str, owned: bool) -> Result<String, String> {
+    let Some(uid) = workstation.jail_uid else {
+        return Ok(path.to_string());
+    };
+
+    let jailed = jailed_path(workstation, path.to_string());
+    let _ = fs::remove_file(&jailed);
+
+    if let Err(e) = fs::hard_link(path, &jailed) {
+        let err_msg = format!(
+            "Failed to link {} into jail for VM ID {} ({} must be on the same filesystem as the image directory): {}",
+            path, workstation.vm_id, JAILER_CHROOT_BASE, e
+        );
+        error!("{}", err_msg);
+        return Err(err_msg);
+    }
+
+    if owned {
+        chown(&jailed, Some(uid), Some(uid))
+            .map_err(|e| format!("Failed to chown {} for VM ID {}: {}", jailed, workstation.vm_id, e))?;
+    }
+
+    Ok(api_path(workstation, &jailed))
+}
+
+// Drops what the jailer sets up on launch so a suspended VM can be jailed again in the same chroot.
+pub fn reset_jail(vm_id: &str) {
+    let _ = fs::remove_dir_all(format!("{}/dev", jail_root(vm_id)));
+    let _ = fs::remove_file(format!("{}/firecracker", jail_root(vm_id)));
+}
+
+pub fn remove_jail(vm_id: &str) {
+    if let Err(e) = fs::remove_dir_all(jail_dir(vm_id))
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        error!("Failed to remove jail for VM ID {}: {}", vm_id, e);
+    }
+}
+
+/*----------------------------------------------------------LAUNCH----------------------------------------------------------*/
+
+// Builds the jailer invocation: chroot, uid/gid drop, cgroup and the VM's network namespace.
+// The cgroup is created with its limits beforehand; the jailer only joins it.
+pub fn jailer_command(workstation: &Workstation, uid: u32, socket_path: &str) -> Command {
+    let mut command = Command::new(JAILER_PATH);
+    command
+        .args(["--id", &workstation.vm_id])
+        .args(["--exec-file", FIRECRACKER_PATH])
+        .args(["--uid", &uid.to_string(), "--gid", &uid.to_string()])
+        .args(["--chroot-base-dir", JAILER_CHROOT_BASE])
+        .args(["--netns", &format!("/var/run/netns/{}", netns_name(workstation.order))])
+        .args(["--cgroup-version", "2"])
+        .args(["--parent-cgroup", CGROUP_SLICE])
+        .args(["--cgroup", &format!("cpu.weight={}", workstation.cgroup.cpu_weight)])
+        .args(["--", "--api-sock", &api_path(workstation, socket_path)]);
+
+    command
+}
+
+/*----------------------------------------------------------NETWORK----------------------------------------------------------*/
+
+// A jailed VM's TAP device lives in its own network namespace, bridged to fc-br0 through a veth pair.
+pub fn connect_jailed_vm_to_network(workstation: &Workstation, uid: u32) -> Result<(), String> {
+    let netns = netns_name(workstation.order);
+    let veth = veth_name(workstation.order);
+    let tap = format!("fc-tap{}", workstation.order - 2);
+    let uid = uid.to_string();
+
+    let steps: Vec<Vec<&str>> = vec![
+        vec!["ip", "netns", "add", &netns],
+        vec!["ip", "link", "add", &veth, "type", "veth", "peer", "name", "veth0", "netns", &netns],
+        vec!["ip", "link", "set", &veth, "master", "fc-br0"],
+        vec!["ip", "link", "set", &veth, "up"],
+        vec!["ip", "netns", "exec", &netns, "ip", "tuntap", "add", "dev", &tap, "mode", "tap", "user", &uid, "group", &uid],
+        vec!["ip", "netns", "exec", &netns, "ip", "link", "add", "br0", "type", "bridge"],
+        vec!["ip", "netns", "exec", &netns, "ip", "link", "set", &tap, "master", "br0"],
+        vec!["ip", "netns", "exec", &netns, "ip", "link", "set", "veth0", "master", "br0"],
+        vec!["ip", "netns", "exec", &netns, "ip", "link", "set", "br0", "up"],
+        vec!["ip", "netns", "exec", &netns, "ip", "link", "set", "veth0", "up"],
+        vec!["ip", "netns", "exec", &netns, "ip", "link", "set", &tap, "up"],
+    ];
+
+    for step in steps {
+        let status = Command::new("sudo").args(&step).status();
+
+        if status.is_err() || !status.unwrap().success() {
+            let err_msg = format!("Failed to set up network namespace {}: {}", netns, step.join(" "));
+            error!("{}", err_msg);
+            let _ = delete_netns(workstation.order);
+            return Err(err_msg);
+        }
+    }
+
+    info!("Successfully created network namespace {} with tap device {}", netns, tap);
+
+    Ok(())
+}
+
+// Removing the namespace also destroys the TAP device and both ends of the veth pair.
+pub fn delete_netns(order: u32) -> Result<(), String> {
+    let netns = netns_name(order);
+    info!("Deleting network namespace: {}", netns);
+
+    let delete_netns = Command::new("sudo")
+        .args(["ip", "netns", "del", &netns])
+        .status();
+
+    if delete_netns.is_err() || !delete_netns.unwrap().success() {
+        return Err(format!("Failed to delete network namespace: {}", netns));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{AppState, Workstation};
+use crate::config::Config;
+use crate::helpers::{
+    firecracker_api,
+    firecracker_socket_path,
+};
+use crate::balloon::update_balloon_target;
+use crate::suspend::ensure_running;
+use crate::validation::{
+    BYTES_PER_MBIT,
+    validate_update_payload,
+};
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+// Token buckets refill over one second, so sizes read as per-second rates.
+const REFILL_TIME_MS: u64 = 1000;
+
+// One direction of a Firecracker rate limiter. Zero leaves that bucket unlimited.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct RateLimit {
+    #[serde(default)]
+    pub bandwidth_mbps: u64,
+    #[serde(default)]
+    pub burst_mbit: u64,
+    #[serde(default)]
+    pub ops_per_sec: u64,
+    #[serde(default)]
+    pub ops_burst: u64,
+}
+
+impl RateLimit {
+    pub fn bandwidth(mbps: u64) -> Self {
+        RateLimit {
+            bandwidth_mbps: mbps,
+            ..Default::default()
+        }
+    }
+
+    // Drive limits as requested, falling back to the host defaults; an explicit 0 lifts the default.
+    pub fn disk(config: &Config, bandwidth_mbps: Option<u64>, iops: Option<u64>) -> Self {
+        RateLimit {
+            bandwidth_mbps: bandwidth_mbps.or(config.disk_bandwidth_mbps).unwrap_or(0),
+            ops_per_sec: iops.or(config.disk_iops).unwrap_or(0),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.bandwidth_mbps == 0 && self.ops_per_sec == 0
+    }
+
+    // A bucket of size 0 is disabled, which is how a PATCH lifts a limit again.
+    pub fn to_firecracker(self) -> Value {
+        serde_json::json!({
+            "bandwidth": {
+                "size": self.bandwidth_mbps.saturating_mul(BYTES_PER_MBIT),
+                "one_time_burst": self.burst_mbit.saturating_mul(BYTES_PER_MBIT),
+                "refill_time": REFILL_TIME_MS,
+            },
+            "ops": {
+                "size": self.ops_per_sec,
+                "one_time_burst": self.ops_burst,
+                "refill_time": REFILL_TIME_MS,
+            },
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateWorkstationPayload {
+    // Shorthand for the same bandwidth in both directions.
+    pub bandwidth: Option<u64>,
+    pub rx_rate_limit: Option<RateLimit>,
+    pub tx_rate_limit: Option<RateLimit>,
+    pub disk_rate_limit: Option<RateLimit>,
+    pub balloon_target_mib: Option<u32>,
+}
+
+/*----------------------------------------------------------UPDATES----------------------------------------------------------*/
+
+pub fn update_network_limits(workstation: &Workstation) -> Result<(), String> {
+    let set_limits = serde_json::json!({
+        "iface_id": "eth0",
+        "rx_rate_limiter": workstation.rx_rate_limit.to_firecracker(),
+        "tx_rate_limiter": workstation.tx_rate_limit.to_firecracker(),
+    });
+
+    firecracker_api(&firecracker_socket_path(workstation), "PATCH", "/network-interfaces/eth0", &set_limits.to_string())
+}
+
+pub fn update_disk_limit(workstation: &Workstation, limit: RateLimit) -> Result<(), String> {
+    let set_limit = serde_json::json!({
+        "drive_id": "rootfs",
+        "rate_limiter": limit.to_firecracker(),
+    });
+
+    firecracker_api(&firecracker_socket_path(workstation), "PATCH", "/drives/rootfs", &set_limit.to_string())
+}
+
+// Applies the requested changes one device at a time. The workstation only takes on what Firecracker
+// accepted, so it still matches the VM when a later step fails.
+fn apply_update(workstation: &mut Workstation, payload: &UpdateWorkstationPayload) -> Result<(), String> {
+    let mut network = workstation.clone();
+
+    if let Some(bandwidth) = payload.bandwidth {
+        network.bandwidth = bandwidth;
+        network.rx_rate_limit.bandwidth_mbps = bandwidth;
+        network.tx_rate_limit.bandwidth_mbps = bandwidth;
+    }
+    if let Some(limit) = payload.rx_rate_limit {
+        network.rx_rate_limit = limit;
+    }
+    if let Some(limit) = payload.tx_rate_limit {
+        network.tx_rate_limit = limit;
+    }
+
+    if network.rx_rate_limit != workstation.rx_rate_limit || network.tx_rate_limit != workstation.tx_rate_limit {
+        update_network_limits(&network)?;
+    }
+    workstation.bandwidth = network.bandwidth;
+    workstation.rx_rate_limit = network.rx_rate_limit;
+    workstation.tx_rate_limit = network.tx_rate_limit;
+
+    if let Some(limit) = payload.disk_rate_limit {
+        update_disk_limit(workstation, limit)?;
+        workstation.disk_rate_limit = limit;
+    }
+
+    if let Some(target_mib) = payload.balloon_target_mib {
+        update_balloon_target(workstation, target_mib)?;
+        if let Some(balloon) = workstation.balloon.as_mut() {
+            balloon.amount_mib = target_mib;
+        }
+    }
+
+    Ok(())
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn update_workstation_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+    Json(payload): Json<UpdateWorkstationPayload>,
+) -> impl IntoResponse {
+    info!("Update request received for ID: {}", workstation_id);
+
+    // Held until the result is written back, so a concurrent PATCH starts from this one's outcome. The lock is
+    // only created under the registry lock for a workstation that exists, so `remove_workstation` always cleans it up.
+    let update_lock = {
+        let workstations = state.workstations.lock().unwrap();
+        if !workstations.contains_key(&workstation_id) {
+            return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+        }
+        state.update_locks.lock().unwrap().entry(workstation_id.clone()).or_default().clone()
+    };
+    let _update_guard = update_lock.lock().await;
+
+    let Some(workstation) = state.workstations.lock().unwrap().get(&workstation_id).cloned() else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    if let Err(errors) = validate_update_payload(&state.config, &workstation, &payload) {
+        let error_response = serde_json::json!({ "error": "Invalid workstation parameters", "fields": errors });
+        return (StatusCode::BAD_REQUEST, Json(error_response)).into_response();
+    }
+
+    if let Err(e) = ensure_running(&state, &workstation_id).await {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"error": e}))).into_response();
+    }
+
+    let update = tokio::task::spawn_blocking(move || {
+        let mut updated = workstation;
+        let result = apply_update(&mut updated, &payload);
+        (updated, result)
+    }).await;
+
+    let (updated, result) = match update {
+        Ok(update) => update,
+        Err(e) => {
+            error!("Update task for workstation {} panicked: {}", workstation_id, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "Update failed"}))).into_response();
+        }
+    };
+
+    let mut workstations_map = state.workstations.lock().unwrap();
+    let Some(workstation) = workstations_map.get_mut(&workstation_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    workstation.bandwidth = updated.bandwidth;
+    workstation.rx_rate_limit = updated.rx_rate_limit;
+    workstation.tx_rate_limit = updated.tx_rate_limit;
+    workstation.disk_rate_limit = updated.disk_rate_limit;
+    workstation.balloon = updated.balloon;
+
+    if let Err(e) = result {
+        error!("Failed to update workstation {}: {}", workstation_id, e);
+        return (StatusCode::CONFLICT, Json(serde_json::json!({"error": e, "workstation": workstation.clone()}))).into_response();
+    }
+
+    (StatusCode::OK, Json(workstation.clone())).into_response()
+}
@@ -1,5 +1,6 @@
 use axum::{
-    routing::{get, post, delete},
+    routing::{get, post, delete, any},
+    middleware,
     Router
 };
 use std::{
@@ -18,6 +19,87 @@ use serde::Serialize;
 use tower_http::cors::{Any, CorsLayer};
 
 mod terminal;
+mod ssh;
+mod exec;
+mod config;
+use config::Config;
+mod sftp;
+mod files;
+mod tunnel;
+mod gateway;
+mod proxy;
+use proxy::{
+    proxy_handler,
+    proxy_root_handler,
+    host_routing_middleware,
+};
+mod metrics;
+use metrics::{
+    Metrics,
+    metrics_handler,
+    track_requests,
+};
+mod vmm;
+use vmm::{
+    VmmMetrics,
+    vmm_metrics_handler,
+};
+mod activity;
+use activity::{
+    ActivityTracker,
+    extend_workstation_handler,
+};
+mod suspend;
+mod capacity;
+mod balloon;
+use balloon::{
+    Balloon,
+    balloon_handler,
+};
+mod limits;
+use limits::{
+    RateLimit,
+    update_workstation_handler,
+};
+mod affinity;
+mod cpu_template;
+use cpu_template::{
+    list_cpu_templates_handler,
+    get_cpu_template_handler,
+    put_cpu_template_handler,
+    delete_cpu_template_handler,
+};
+use affinity::{
+    CoreAllocator,
+    CpuAffinity,
+};
+mod cgroup;
+use cgroup::CgroupLimits;
+mod volumes;
+use volumes::{
+    Volume,
+    VolumeAttachment,
+    load_volumes,
+    list_volumes_handler,
+    create_volume_handler,
+    get_volume_handler,
+    delete_volume_handler,
+};
+mod pool;
+mod golden;
+mod jailer;
+use pool::WarmPool;
+mod validation;
+mod usage;
+use usage::{
+    UsageHistory,
+    usage_handler,
+};
+mod ports;
+use ports::{
+    PortMapping,
+    PortForward,
+};
 
 mod handlers;
 use handlers::{
@@ -25,7 +107,21 @@ use handlers::{
     get_workstations_handler,
     create_workstation_handler,
     delete_workstation_handler,
+    exec_workstation_handler,
+    download_file_handler,
+    upload_file_handler,
+    list_dir_handler,
+    stat_path_handler,
+    mkdir_handler,
+    rename_handler,
+    delete_path_handler,
+    list_ports_handler,
+    publish_port_handler,
+    unpublish_port_handler,
+    add_ssh_key_handler,
     terminal_ws_handler,
+    exec_ws_handler,
+    tunnel_ws_handler,
 };
 
 mod helpers;
@@ -40,33 +136,103 @@ pub const IMAGE_PATH: &str = "/root/projects/firecrack-project/";
 pub const KERNEL_IMAGE_PATH: &str = "/root/projects/firecrack-project/kernel.bin";
 pub const ROOTFS_IMAGE_PATH: &str = "/root/projects/firecrack-project/rootfs.ext4";
 
+pub const FIRECRACKER_PATH: &str = "/usr/local/bin/firecracker";
+pub const JAILER_PATH: &str = "/usr/local/bin/jailer";
+pub const JAILER_CHROOT_BASE: &str = "/srv/jailer";
+
+pub const SSH_USERNAME: &str = "root";
+pub const SSH_PASSWORD: &str = "root";
+pub const DEFAULT_EXEC_TIMEOUT_SECS: u64 = 60;
+
 /*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Default)]
 struct CreateWorkstationPayload {
     vcpu_count: u64,
     mem_size_mib: u32,
     smt_enabled: bool,
     read_only: bool,
     bandwidth: u64,
+    #[serde(default)]
+    ssh_public_keys: Vec<String>,
+    #[serde(default)]
+    ttl: Option<u64>,
+    #[serde(default)]
+    idle_timeout: Option<u64>,
+    #[serde(default)]
+    cpu_shares: Option<u64>,
+    #[serde(default)]
+    cpu_affinity: Option<CpuAffinity>,
+    #[serde(default)]
+    cpu_template: Option<String>,
+    #[serde(default)]
+    balloon: Option<Balloon>,
+    #[serde(default)]
+    volumes: Vec<VolumeAttachment>,
+    #[serde(default)]
+    disk_bandwidth_mbps: Option<u64>,
+    #[serde(default)]
+    disk_iops: Option<u64>,
+    #[serde(default)]
+    disk_size_gib: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+enum WorkstationStatus {
+    Running,
+    Suspending,
+    Suspended,
+    Resuming,
 }
 
 #[derive(Serialize, Clone)]
 struct Workstation {
     id: String,
+    // Names the VM's socket, images and snapshot files; differs from `id` once a pooled VM is claimed.
+    #[serde(skip)]
+    vm_id: String,
     ip_address: String,
     order: u32,
     vcpu_count: u64,
     mem_size_mib: u32,
     smt_enabled: bool,
     read_only: bool,
+    disk_size_gib: Option<u64>,
     bandwidth: u64,
+    rx_rate_limit: RateLimit,
+    tx_rate_limit: RateLimit,
+    disk_rate_limit: RateLimit,
+    balloon: Option<Balloon>,
+    volumes: Vec<VolumeAttachment>,
+    ports: Vec<PortMapping>,
+    ssh_public_keys: Vec<String>,
+    ttl: Option<u64>,
+    idle_timeout: Option<u64>,
+    expires_at: Option<u64>,
+    status: WorkstationStatus,
+    cgroup: CgroupLimits,
+    cpu_affinity: Option<CpuAffinity>,
+    cpu_template: Option<String>,
+    // Set when the VMM runs under the jailer as this uid/gid, in its own chroot and network namespace.
+    #[serde(skip)]
+    jail_uid: Option<u32>,
 }
 
 #[derive(Clone)]
 struct AppState {
     workstations: Arc<Mutex<HashMap<String, Workstation>>>,
     vm_counter: Arc<Mutex<u32>>,
+    config: Arc<Config>,
+    port_forwards: Arc<Mutex<HashMap<u16, PortForward>>>,
+    metrics: Arc<Metrics>,
+    vmm_metrics: Arc<Mutex<HashMap<String, VmmMetrics>>>,
+    usage: Arc<Mutex<HashMap<String, UsageHistory>>>,
+    activity: Arc<ActivityTracker>,
+    warm_pool: Arc<WarmPool>,
+    cores: Arc<CoreAllocator>,
+    volumes: Arc<Mutex<HashMap<String, Volume>>>,
+    // Serializes PATCHes to the same workstation so one update never writes back over another.
+    update_locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
 }
 
 /*----------------------------------------------------------MAIN----------------------------------------------------------*/
@@ -75,6 +241,22 @@ struct AppState {
 async fn main() {
     tracing_subscriber::fmt::init();
 
+    let args = std::env::args().collect::<Vec<_>>();
+
+    if args.get(1).map(String::as_str) == Some("tunnel") {
+        if let Err(e) = tunnel::tunnel_command(&args[2..]).await {
+            error!("{}", e);
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("golden") {
+        if let Err(e) = golden::golden_command(&args[2..]).await {
+            error!("{}", e);
+        }
+        return;
+    }
+
     info!("Starting Firebender API server...");
 
     if let Err(e) = create_bridge() {
@@ -85,6 +267,16 @@ async fn main() {
     let app_state = AppState {
         workstations: Arc::new(Mutex::new(HashMap::new())),
         vm_counter: Arc::new(Mutex::new(2)),
+        config: Arc::new(Config::from_env()),
+        port_forwards: Arc::new(Mutex::new(HashMap::new())),
+        metrics: Arc::new(Metrics::new()),
+        vmm_metrics: Arc::new(Mutex::new(HashMap::new())),
+        usage: Arc::new(Mutex::new(HashMap::new())),
+        activity: Arc::new(ActivityTracker::new()),
+        warm_pool: Arc::new(WarmPool::new()),
+        cores: Arc::new(CoreAllocator::new()),
+        volumes: Arc::new(Mutex::new(load_volumes())),
+        update_locks: Arc::new(Mutex::new(HashMap::new())),
     };
 
     let cors_layer = CorsLayer::new()
@@ -94,13 +286,44 @@ async fn main() {
 
     let app = Router::new()
     .route("/", get(root_handler))
+    .route("/metrics", get(metrics_handler))
     .route("/workstations", get(get_workstations_handler))
     .route("/workstations", post(create_workstation_handler))
-    .route("/workstations/{id}", delete(delete_workstation_handler))
+    .route("/workstations/{id}", delete(delete_workstation_handler).patch(update_workstation_handler))
+    .route("/workstations/{id}/exec", post(exec_workstation_handler))
+    .route("/workstations/{id}/files", get(download_file_handler).put(upload_file_handler))
+    .route("/workstations/{id}/fs", get(list_dir_handler).delete(delete_path_handler))
+    .route("/workstations/{id}/fs/stat", get(stat_path_handler))
+    .route("/workstations/{id}/fs/mkdir", post(mkdir_handler))
+    .route("/workstations/{id}/fs/rename", post(rename_handler))
+    .route("/workstations/{id}/ports", get(list_ports_handler).post(publish_port_handler))
+    .route("/workstations/{id}/ports/{host_port}", delete(unpublish_port_handler))
+    .route("/workstations/{id}/keys", post(add_ssh_key_handler))
+    .route("/workstations/{id}/metrics", get(vmm_metrics_handler))
+    .route("/workstations/{id}/usage", get(usage_handler))
+    .route("/workstations/{id}/extend", post(extend_workstation_handler))
+    .route("/workstations/{id}/balloon", get(balloon_handler))
+    .route("/volumes", get(list_volumes_handler).post(create_volume_handler))
+    .route("/volumes/{id}", get(get_volume_handler).delete(delete_volume_handler))
+    .route("/cpu-templates", get(list_cpu_templates_handler))
+    .route("/cpu-templates/{name}", get(get_cpu_template_handler).put(put_cpu_template_handler).delete(delete_cpu_template_handler))
     .route("/ws/workstations/{id}/terminal", get(terminal_ws_handler))
+    .route("/ws/workstations/{id}/exec", get(exec_ws_handler))
+    .route("/ws/workstations/{id}/tunnel/{port}", get(tunnel_ws_handler))
+    .route("/proxy/{id}/{port}", any(proxy_root_handler))
+    .route("/proxy/{id}/{port}/", any(proxy_root_handler))
+    .route("/proxy/{id}/{port}/{*path}", any(proxy_handler))
+    .route_layer(middleware::from_fn_with_state(app_state.clone(), track_requests))
+    .layer(middleware::from_fn_with_state(app_state.clone(), host_routing_middleware))
     .with_state(app_state.clone())
     .layer(cors_layer);
 
+    tokio::spawn(gateway::run_ssh_gateway(app_state.clone()));
+    tokio::spawn(vmm::collect_vmm_metrics(app_state.clone()));
+    tokio::spawn(usage::collect_usage(app_state.clone()));
+    tokio::spawn(activity::run_reaper(app_state.clone()));
+    tokio::spawn(pool::run_refill(app_state.clone()));
+
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     info!("Server listening on http://{}", addr);
 
@@ -0,0 +1,238 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
+
+use crate::{AppState, WorkstationStatus};
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const LATENCY_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+pub struct Histogram {
+    counts: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            counts: [0; LATENCY_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if value <= *bound {
+                self.counts[i] += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str) {
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, self.counts[i]);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+pub struct Metrics {
+    api_requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    provisioning_failures: Mutex<BTreeMap<String, u64>>,
+    create_latency: Mutex<Histogram>,
+    delete_latency: Mutex<Histogram>,
+    provisioning: AtomicI64,
+    terminal_sessions: AtomicI64,
+}
+
+// Keeps a gauge raised for as long as the guard is alive.
+pub struct GaugeGuard<'a>(&'a AtomicI64);
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            api_requests: Mutex::new(BTreeMap::new()),
+            provisioning_failures: Mutex::new(BTreeMap::new()),
+            create_latency: Mutex::new(Histogram::new()),
+            delete_latency: Mutex::new(Histogram::new()),
+            provisioning: AtomicI64::new(0),
+            terminal_sessions: AtomicI64::new(0),
+        }
+    }
+
+    pub fn track_provisioning(&self) -> GaugeGuard<'_> {
+        self.provisioning.fetch_add(1, Ordering::Relaxed);
+        GaugeGuard(&self.provisioning)
+    }
+
+    pub fn track_terminal_session(&self) -> GaugeGuard<'_> {
+        self.terminal_sessions.fetch_add(1, Ordering::Relaxed);
+        GaugeGuard(&self.terminal_sessions)
+    }
+
+    pub fn record_request(&self, route: &str, method: &str, status: u16) {
+        let mut api_requests = self.api_requests.lock().unwrap();
+        *api_requests.entry((route.to_string(), method.to_string(), status)).or_insert(0) += 1;
+    }
+
+    pub fn record_provisioning_failure(&self, step: &str) {
+        let mut failures = self.provisioning_failures.lock().unwrap();
+        *failures.entry(step.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn observe_create(&self, elapsed: Duration) {
+        self.create_latency.lock().unwrap().observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_delete(&self, elapsed: Duration) {
+        self.delete_latency.lock().unwrap().observe(elapsed.as_secs_f64());
+    }
+}
+
+/*----------------------------------------------------------RENDER----------------------------------------------------------*/
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+pub fn render(state: &AppState) -> String {
+    let metrics = &state.metrics;
+    let mut out = String::new();
+
+    let workstations = state.workstations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+
+    header(&mut out, "firebender_workstations", "gauge", "Number of workstations by status.");
+    for (label, status) in [
+        ("running", WorkstationStatus::Running),
+        ("suspending", WorkstationStatus::Suspending),
+        ("suspended", WorkstationStatus::Suspended),
+        ("resuming", WorkstationStatus::Resuming),
+    ] {
+        let count = workstations.iter().filter(|w| w.status == status).count();
+        let _ = writeln!(out, "firebender_workstations{{status=\"{}\"}} {}", label, count);
+    }
+    let _ = writeln!(out, "firebender_workstations{{status=\"provisioning\"}} {}", metrics.provisioning.load(Ordering::Relaxed));
+
+    header(&mut out, "firebender_workstation_create_duration_seconds", "histogram", "Time taken to create a workstation.");
+    metrics.create_latency.lock().unwrap().render(&mut out, "firebender_workstation_create_duration_seconds");
+
+    header(&mut out, "firebender_workstation_delete_duration_seconds", "histogram", "Time taken to delete a workstation.");
+    metrics.delete_latency.lock().unwrap().render(&mut out, "firebender_workstation_delete_duration_seconds");
+
+    header(&mut out, "firebender_provisioning_failures_total", "counter", "Workstation provisioning failures by step.");
+    for (step, count) in metrics.provisioning_failures.lock().unwrap().iter() {
+        let _ = writeln!(out, "firebender_provisioning_failures_total{{step=\"{}\"}} {}", step, count);
+    }
+
+    header(&mut out, "firebender_terminal_sessions", "gauge", "Active terminal sessions.");
+    let _ = writeln!(out, "firebender_terminal_sessions {}", metrics.terminal_sessions.load(Ordering::Relaxed));
+
+    header(&mut out, "firebender_api_requests_total", "counter", "API requests by route, method and status.");
+    for ((route, method, status), count) in metrics.api_requests.lock().unwrap().iter() {
+        let _ = writeln!(out, "firebender_api_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}", route, method, status, count);
+    }
+
+    header(&mut out, "firebender_workstation_vcpus", "gauge", "vCPUs assigned to each workstation.");
+    for w in &workstations {
+        let _ = writeln!(out, "firebender_workstation_vcpus{{id=\"{}\"}} {}", w.id, w.vcpu_count);
+    }
+
+    header(&mut out, "firebender_workstation_memory_bytes", "gauge", "Memory assigned to each workstation.");
+    for w in &workstations {
+        let _ = writeln!(out, "firebender_workstation_memory_bytes{{id=\"{}\"}} {}", w.id, w.mem_size_mib as u64 * 1024 * 1024);
+    }
+
+    header(&mut out, "firebender_workstation_bandwidth_mbps", "gauge", "Network bandwidth limit of each workstation (0 means unlimited).");
+    for w in &workstations {
+        let _ = writeln!(out, "firebender_workstation_bandwidth_mbps{{id=\"{}\"}} {}", w.id, w.bandwidth);
+    }
+
+    header(&mut out, "firebender_workstation_published_ports", "gauge", "Host ports published for each workstation.");
+    for w in &workstations {
+        let _ = writeln!(out, "firebender_workstation_published_ports{{id=\"{}\"}} {}", w.id, w.ports.len());
+    }
+
+    header(&mut out, "firebender_warm_pool_size", "gauge", "Pre-booted workstations in the warm pool by profile and state.");
+    for (profile, ready, booting) in state.warm_pool.sizes() {
+        let _ = writeln!(out, "firebender_warm_pool_size{{profile=\"{}\",state=\"ready\"}} {}", profile, ready);
+        let _ = writeln!(out, "firebender_warm_pool_size{{profile=\"{}\",state=\"booting\"}} {}", profile, booting);
+    }
+
+    header(&mut out, "firebender_warm_pool_target", "gauge", "Configured warm pool size by profile.");
+    for profile in &state.config.warm_pool {
+        let _ = writeln!(out, "firebender_warm_pool_target{{profile=\"{}\"}} {}", profile.key(), profile.size);
+    }
+
+    header(&mut out, "firebender_warm_pool_claims_total", "counter", "Workstation creations served from the warm pool (hit) or cold booted (miss).");
+    let _ = writeln!(out, "firebender_warm_pool_claims_total{{result=\"hit\"}} {}", state.warm_pool.hits());
+    let _ = writeln!(out, "firebender_warm_pool_claims_total{{result=\"miss\"}} {}", state.warm_pool.misses());
+
+    header(&mut out, "firebender_vmm_counter_total", "counter", "Firecracker device and vCPU counters by workstation, group and metric.");
+    for (id, vmm) in state.vmm_metrics.lock().unwrap().iter() {
+        for (name, value) in &vmm.counters {
+            let (group, metric) = name.split_once('.').unwrap_or(("", name));
+            let _ = writeln!(out, "firebender_vmm_counter_total{{id=\"{}\",group=\"{}\",metric=\"{}\"}} {}", id, group, metric, value);
+        }
+    }
+
+    header(&mut out, "firebender_vmm_gauge", "gauge", "Firecracker latency aggregates from the last metrics flush by workstation, group and metric.");
+    for (id, vmm) in state.vmm_metrics.lock().unwrap().iter() {
+        for (name, value) in &vmm.gauges {
+            let (group, metric) = name.split_once('.').unwrap_or(("", name));
+            let _ = writeln!(out, "firebender_vmm_gauge{{id=\"{}\",group=\"{}\",metric=\"{}\"}} {}", id, group, metric, value);
+        }
+    }
+
+    out
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(&state),
+    )
+}
+
+pub async fn track_requests(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let route = req.extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+
+    let response = next.run(req).await;
+
+    state.metrics.record_request(&route, &method, response.status().as_u16());
+
+    response
+}
@@ -0,0 +1,406 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+use tracing::{
+    info,
+    error,
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    CreateWorkstationPayload,
+    Workstation,
+    WorkstationStatus,
+};
+use crate::capacity::check_capacity;
+use crate::cgroup::{
+    CgroupLimits,
+    DEFAULT_CPU_WEIGHT,
+    set_cpu_weight,
+};
+use crate::helpers::{
+    next_order,
+    provision_vm,
+    shutdown_vm,
+    unix_now,
+};
+use crate::limits::{
+    RateLimit,
+    update_disk_limit,
+    update_network_limits,
+};
+use crate::ssh::{
+    connect_to_workstation,
+    close_session,
+};
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const REFILL_INTERVAL_SECS: u64 = 10;
+const READY_TIMEOUT_SECS: u64 = 60;
+
+// A VM shape kept pre-booted, written as `<vcpus>x<mem_mib>=<count>` in FIREBENDER_WARM_POOL.
+#[derive(Clone)]
+pub struct PoolProfile {
+    pub vcpu_count: u64,
+    pub mem_size_mib: u32,
+    pub size: usize,
+}
+
+impl PoolProfile {
+    pub fn key(&self) -> String {
+        format!("{}x{}", self.vcpu_count, self.mem_size_mib)
+    }
+
+    // Pooled VMs are writable, unpinned and boot without SMT, a CPU template, a balloon, volumes or a grown disk; bandwidth is applied when claimed.
+    fn matches(&self, payload: &CreateWorkstationPayload) -> bool {
+        payload.vcpu_count == self.vcpu_count
+            && payload.mem_size_mib == self.mem_size_mib
+            && !payload.smt_enabled
+            && !payload.read_only
+            && payload.cpu_affinity.is_none()
+            && payload.cpu_template.is_none()
+            && payload.balloon.is_none()
+            && payload.volumes.is_empty()
+            && payload.disk_size_gib.is_none()
+    }
+
+    fn payload(&self) -> CreateWorkstationPayload {
+        CreateWorkstationPayload {
+            vcpu_count: self.vcpu_count,
+            mem_size_mib: self.mem_size_mib,
+            ..Default::default()
+        }
+    }
+}
+
+pub fn parse_profiles(spec: &str) -> Result<Vec<PoolProfile>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|profile| !profile.is_empty())
+        .map(|profile| {
+            let (shape, size) = profile.split_once('=').unwrap_or((profile, "1"));
+            let (vcpus, memory) = shape.split_once('x')
+                .ok_or(format!("Invalid warm pool profile: {}", profile))?;
+
+            Ok(PoolProfile {
+                vcpu_count: vcpus.parse().map_err(|_| format!("Invalid vCPU count in warm pool profile: {}", profile))?,
+                mem_size_mib: memory.parse().map_err(|_| format!("Invalid memory size in warm pool profile: {}", profile))?,
+                size: size.parse().map_err(|_| format!("Invalid pool size in warm pool profile: {}", profile))?,
+            })
+        })
+        .collect()
+}
+
+struct PooledVm {
+    profile: String,
+    workstation: Workstation,
+    ready: bool,
+}
+
+pub struct WarmPool {
+    vms: Mutex<Vec<PooledVm>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    refill: Notify,
+}
+
+impl WarmPool {
+    pub fn new() -> Self {
+        WarmPool {
+            vms: Mutex::new(Vec::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            refill: Notify::new(),
+        }
+    }
+
+    fn take(&self, profile: &str) -> Option<Workstation> {
+        let mut vms = self.vms.lock().unwrap();
+        let index = vms.iter().position(|vm| vm.ready && vm.profile == profile)?;
+        Some(vms.remove(index).workstation)
+    }
+
+    fn count(&self, profile: &str) -> usize {
+        self.vms.lock().unwrap().iter().filter(|vm| vm.profile == profile).count()
+    }
+
+    fn mark_ready(&self, vm_id: &str) {
+        if let Some(vm) = self.vms.lock().unwrap().iter_mut().find(|vm| vm.workstation.vm_id == vm_id) {
+            vm.ready = true;
+        }
+    }
+
+    fn discard(&self, vm_id: &str) {
+        self.vms.lock().unwrap().retain(|vm| vm.workstation.vm_id != vm_id);
+    }
+
+    // Every pooled VM, booting or ready, so capacity and cleanup can account for them.
+    pub fn workstations(&self) -> Vec<Workstation> {
+        self.vms.lock().unwrap().iter().map(|vm| vm.workstation.clone()).collect()
+    }
+
+    // (profile, ready, booting) for every profile that currently has VMs.
+    pub fn sizes(&self) -> Vec<(String, usize, usize)> {
+        let vms = self.vms.lock().unwrap();
+        let mut sizes: Vec<(String, usize, usize)> = Vec::new();
+
+        for vm in vms.iter() {
+            let index = match sizes.iter().position(|(profile, _, _)| *profile == vm.profile) {
+                Some(index) => index,
+                None => {
+                    sizes.push((vm.profile.clone(), 0, 0));
+                    sizes.len() - 1
+                }
+            };
+
+            if vm.ready {
+                sizes[index].1 += 1;
+            } else {
+                sizes[index].2 += 1;
+            }
+        }
+
+        sizes
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+/*----------------------------------------------------------CLAIM----------------------------------------------------------*/
+
+// Hands out a pre-booted VM for the request, re-keyed with a fresh ID and the caller's settings.
+pub async fn claim_workstation(state: &AppState, payload: &CreateWorkstationPayload) -> Option<Workstation> {
+    let profiles = &state.config.warm_pool;
+    if profiles.is_empty() {
+        return None;
+    }
+
+    let claimed = profiles.iter()
+        .find(|profile| profile.matches(payload))
+        .and_then(|profile| state.warm_pool.take(&profile.key()));
+
+    let Some(mut workstation) = claimed else {
+        state.warm_pool.misses.fetch_add(1, Ordering::Relaxed);
+        return None;
+    };
+
+    state.warm_pool.hits.fetch_add(1, Ordering::Relaxed);
+    state.warm_pool.refill.notify_one();
+
+    workstation.id = Uuid::new_v4().to_string();
+    workstation.ssh_public_keys = payload.ssh_public_keys.clone();
+    workstation.ttl = payload.ttl;
+    workstation.idle_timeout = payload.idle_timeout;
+    workstation.expires_at = payload.ttl.map(|ttl| unix_now() + ttl);
+    workstation.bandwidth = payload.bandwidth;
+    workstation.rx_rate_limit = RateLimit::bandwidth(payload.bandwidth);
+    workstation.tx_rate_limit = RateLimit::bandwidth(payload.bandwidth);
+    workstation.cgroup.cpu_weight = payload.cpu_shares.unwrap_or(DEFAULT_CPU_WEIGHT);
+
+    let disk_rate_limit = RateLimit::disk(&state.config, payload.disk_bandwidth_mbps, payload.disk_iops);
+
+    // Applying the settings talks to Firecracker and may have to shut the VM down, so it runs off the async workers.
+    let applied = tokio::task::spawn_blocking(move || apply_claim(workstation, disk_rate_limit)).await;
+
+    match applied {
+        Ok(workstation) => workstation,
+        Err(e) => {
+            error!("Warm pool claim task panicked: {}", e);
+            None
+        }
+    }
+}
+
+fn apply_claim(mut workstation: Workstation, disk_rate_limit: RateLimit) -> Option<Workstation> {
+    if workstation.bandwidth > 0 && let Err(e) = update_network_limits(&workstation) {
+        error!("Failed to apply bandwidth to pooled VM {}: {}", workstation.vm_id, e);
+        let _ = shutdown_vm(&workstation);
+        return None;
+    }
+
+    if disk_rate_limit != workstation.disk_rate_limit {
+        if let Err(e) = update_disk_limit(&workstation, disk_rate_limit) {
+            error!("Failed to apply disk limits to pooled VM {}: {}", workstation.vm_id, e);
+            let _ = shutdown_vm(&workstation);
+            return None;
+        }
+        workstation.disk_rate_limit = disk_rate_limit;
+    }
+
+    if let Err(e) = set_cpu_weight(&workstation) {
+        error!("Failed to apply CPU shares to pooled VM {}: {}", workstation.vm_id, e);
+    }
+
+    info!("Claimed pooled VM {} as workstation {}", workstation.vm_id, workstation.id);
+
+    Some(workstation)
+}
+
+/*----------------------------------------------------------REFILL----------------------------------------------------------*/
+
+fn provision_pooled_vm(state: &AppState, profile: &PoolProfile) -> Result<Workstation, String> {
+    let mut vm_counter = state.vm_counter.lock().unwrap();
+
+    check_capacity(state, &profile.payload()).map_err(|shortage| shortage.message())?;
+
+    let order = next_order(&mut vm_counter)?;
+
+    let vm_id = Uuid::new_v4().to_string();
+
+    let workstation = Workstation {
+        id: vm_id.clone(),
+        vm_id,
+        ip_address: format!("172.16.0.{}", order),
+        order,
+        vcpu_count: profile.vcpu_count,
+        mem_size_mib: profile.mem_size_mib,
+        smt_enabled: false,
+        read_only: false,
+        disk_size_gib: None,
+        bandwidth: 0,
+        rx_rate_limit: RateLimit::default(),
+        tx_rate_limit: RateLimit::default(),
+        disk_rate_limit: RateLimit::disk(&state.config, None, None),
+        balloon: None,
+        volumes: Vec::new(),
+        ports: Vec::new(),
+        ssh_public_keys: Vec::new(),
+        ttl: None,
+        idle_timeout: None,
+        expires_at: None,
+        status: WorkstationStatus::Running,
+        cgroup: CgroupLimits::for_payload(&state.config, &profile.payload()),
+        cpu_affinity: None,
+        cpu_template: None,
+        jail_uid: state.config.jail_uid(order),
+    };
+
+    if let Err((step, e)) = provision_vm(&workstation) {
+        state.metrics.record_provisioning_failure(step);
+        return Err(e);
+    }
+
+    // Registered while still holding `vm_counter` so capacity checks see it straight away.
+    state.warm_pool.vms.lock().unwrap().push(PooledVm {
+        profile: profile.key(),
+        workstation: workstation.clone(),
+        ready: false,
+    });
+
+    Ok(workstation)
+}
+
+async fn wait_until_ready(ip_address: &str) -> bool {
+    for _ in 0..READY_TIMEOUT_SECS {
+        if let Ok(session) = connect_to_workstation(ip_address).await {
+            close_session(&session).await;
+            return true;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    false
+}
+
+async fn refill_profile(state: &AppState, profile: &PoolProfile) {
+    while state.warm_pool.count(&profile.key()) < profile.size {
+        let provisioned = tokio::task::spawn_blocking({
+            let state = state.clone();
+            let profile = profile.clone();
+            move || provision_pooled_vm(&state, &profile)
+        }).await;
+
+        let workstation = match provisioned {
+            Ok(Ok(workstation)) => workstation,
+            Ok(Err(e)) => {
+                error!("Failed to add VM to warm pool {}: {}", profile.key(), e);
+                return;
+            },
+            Err(e) => {
+                error!("Warm pool task panicked: {}", e);
+                return;
+            }
+        };
+
+        if wait_until_ready(&workstation.ip_address).await {
+            info!("Pooled VM {} ({}) is ready", workstation.vm_id, profile.key());
+            state.warm_pool.mark_ready(&workstation.vm_id);
+        } else {
+            error!("Pooled VM {} did not become ready. Discarding...", workstation.vm_id);
+            state.warm_pool.discard(&workstation.vm_id);
+            let _ = tokio::task::spawn_blocking(move || shutdown_vm(&workstation)).await;
+            return;
+        }
+    }
+}
+
+pub async fn run_refill(state: AppState) {
+    if state.config.warm_pool.is_empty() {
+        return;
+    }
+
+    for profile in &state.config.warm_pool {
+        info!("Keeping {} pre-booted workstations of profile {}", profile.size, profile.key());
+    }
+
+    loop {
+        for profile in &state.config.warm_pool {
+            refill_profile(&state, profile).await;
+        }
+
+        tokio::select! {
+            _ = state.warm_pool.refill.notified() => {},
+            _ = tokio::time::sleep(Duration::from_secs(REFILL_INTERVAL_SECS)) => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profiles_with_and_without_sizes() {
+        let profiles = parse_profiles(" 2x1024=3, 4x4096 ,").unwrap();
+
+        assert_eq!(profiles.len(), 2);
+        assert_eq!((profiles[0].vcpu_count, profiles[0].mem_size_mib, profiles[0].size), (2, 1024, 3));
+        assert_eq!((profiles[1].vcpu_count, profiles[1].mem_size_mib, profiles[1].size), (4, 4096, 1));
+        assert_eq!(profiles[1].key(), "4x4096");
+    }
+
+    #[test]
+    fn an_empty_spec_disables_the_pool() {
+        assert!(parse_profiles("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_profiles() {
+        assert!(parse_profiles("2-1024").is_err());
+        assert!(parse_profiles("twox1024").is_err());
+        assert!(parse_profiles("2x1024=many").is_err());
+        assert!(parse_profiles("2x1024,4x").is_err());
+    }
+
+    #[test]
+    fn only_plain_requests_match_a_profile() {
+        let profile = parse_profiles("2x1024").unwrap().remove(0);
+
+        assert!(profile.matches(&profile.payload()));
+        assert!(!profile.matches(&CreateWorkstationPayload { read_only: true, ..profile.payload() }));
+        assert!(!profile.matches(&CreateWorkstationPayload { mem_size_mib: 2048, ..profile.payload() }));
+    }
+}
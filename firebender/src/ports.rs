@@ -0,0 +1,180 @@
+use axum::http::StatusCode;
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use tokio::{
+    io::copy_bidirectional,
+    net::{TcpListener, TcpStream},
+    task::{JoinHandle, JoinSet},
+};
+use tracing::{
+    info,
+    error,
+};
+
+use crate::AppState;
+use crate::activity::SessionGuard;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+#[derive(Serialize, Clone)]
+pub struct PortMapping {
+    pub host_port: u16,
+    pub guest_port: u16,
+    pub protocol: &'static str,
+}
+
+pub struct PortForward {
+    pub workstation_id: String,
+    // The accept loop; aborting it also closes every connection it forwarded.
+    task: JoinHandle<()>,
+}
+
+/*----------------------------------------------------------PORTS----------------------------------------------------------*/
+
+async fn forward_connection(mut inbound: TcpStream, peer: SocketAddr, guest_addr: String, _in_use: SessionGuard) {
+    let mut outbound = match TcpStream::connect(&guest_addr).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Failed to connect to {} for {}: {}", guest_addr, peer, e);
+            return;
+        }
+    };
+
+    match copy_bidirectional(&mut inbound, &mut outbound).await {
+        Ok((tx, rx)) => info!("Connection {} -> {} closed ({} bytes out, {} bytes in)", peer, guest_addr, tx, rx),
+        Err(e) => error!("Connection {} -> {} failed: {}", peer, guest_addr, e),
+    }
+}
+
+pub async fn publish_port(
+    state: &AppState,
+    workstation_id: &str,
+    host_port: Option<u16>,
+    guest_port: u16,
+) -> Result<PortMapping, (StatusCode, String)> {
+    let ip_address = {
+        let workstations = state.workstations.lock().unwrap();
+        match workstations.get(workstation_id) {
+            Some(w) => w.ip_address.clone(),
+            None => return Err((StatusCode::NOT_FOUND, format!("Workstation with ID {} not found", workstation_id))),
+        }
+    };
+
+    if guest_port == 0 {
+        return Err((StatusCode::BAD_REQUEST, "Guest port must be > 0.".to_string()));
+    }
+
+    let bind_addr = format!("{}:{}", state.config.publish_address, host_port.unwrap_or(0));
+
+    let listener = match TcpListener::bind(&bind_addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            let err_msg = format!("Failed to bind host port {}: {}", bind_addr, e);
+            error!("{}", err_msg);
+            return Err((StatusCode::CONFLICT, err_msg));
+        }
+    };
+
+    let host_port = match listener.local_addr() {
+        Ok(addr) => addr.port(),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read bound address: {}", e))),
+    };
+
+    let guest_addr = format!("{}:{}", ip_address, guest_port);
+    info!("Publishing {} on host port {}", guest_addr, host_port);
+
+    let activity = state.activity.clone();
+    let forward_id = workstation_id.to_string();
+
+    let task = tokio::spawn(async move {
+        // Open connections live in the set, so aborting this task on unpublish drops them along with the listener.
+        let mut connections = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((inbound, peer)) => {
+                        connections.spawn(forward_connection(inbound, peer, guest_addr.clone(), activity.open_session(&forward_id)));
+                    },
+                    Err(e) => {
+                        error!("Failed to accept connection on host port {}: {}", host_port, e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                },
+                Some(_) = connections.join_next(), if !connections.is_empty() => {},
+            }
+        }
+    });
+
+    let mapping = PortMapping {
+        host_port,
+        guest_port,
+        protocol: "tcp",
+    };
+
+    state.port_forwards.lock().unwrap().insert(host_port, PortForward {
+        workstation_id: workstation_id.to_string(),
+        task,
+    });
+
+    let registered = {
+        let mut workstations = state.workstations.lock().unwrap();
+        match workstations.get_mut(workstation_id) {
+            Some(workstation) => {
+                workstation.ports.push(mapping.clone());
+                true
+            },
+            None => false,
+        }
+    };
+
+    // The workstation may have been deleted while the listener was being set up.
+    if !registered {
+        if let Some(forward) = state.port_forwards.lock().unwrap().remove(&host_port) {
+            forward.task.abort();
+        }
+        return Err((StatusCode::NOT_FOUND, format!("Workstation with ID {} not found", workstation_id)));
+    }
+
+    Ok(mapping)
+}
+
+pub fn unpublish_port(state: &AppState, workstation_id: &str, host_port: u16) -> Result<(), (StatusCode, String)> {
+    {
+        let mut port_forwards = state.port_forwards.lock().unwrap();
+
+        match port_forwards.get(&host_port) {
+            Some(forward) if forward.workstation_id == workstation_id => {},
+            _ => return Err((StatusCode::NOT_FOUND, format!("Host port {} is not published for workstation {}", host_port, workstation_id))),
+        }
+
+        if let Some(forward) = port_forwards.remove(&host_port) {
+            forward.task.abort();
+        }
+    }
+
+    if let Some(workstation) = state.workstations.lock().unwrap().get_mut(workstation_id) {
+        workstation.ports.retain(|p| p.host_port != host_port);
+    }
+
+    info!("Unpublished host port {} for workstation {}", host_port, workstation_id);
+
+    Ok(())
+}
+
+pub fn unpublish_all_ports(state: &AppState, workstation_id: &str) {
+    let mut port_forwards = state.port_forwards.lock().unwrap();
+
+    port_forwards.retain(|host_port, forward| {
+        if forward.workstation_id != workstation_id {
+            return true;
+        }
+
+        info!("Unpublishing host port {} for workstation {}", host_port, workstation_id);
+        forward.task.abort();
+        false
+    });
+}
@@ -0,0 +1,199 @@
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{HeaderValue, StatusCode, Uri, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use hyper::client::conn::http1;
+use hyper_util::rt::TokioIo;
+use regex::Regex;
+use tokio::net::TcpStream;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::AppState;
+
+/*----------------------------------------------------------PROXY----------------------------------------------------------*/
+
+const HOP_BY_HOP_HEADERS: [header::HeaderName; 7] = [
+    header::CONNECTION,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+fn error_response(status: StatusCode, err: String) -> Response {
+    error!("{}", err);
+    (status, Json(serde_json::json!({ "error": err }))).into_response()
+}
+
+fn is_upgrade(req: &Request) -> bool {
+    req.headers()
+        .get(header::CONNECTION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_ascii_lowercase().contains("upgrade"))
+        && req.headers().contains_key(header::UPGRADE)
+}
+
+async fn forward(state: &AppState, workstation_id: &str, port: u16, path: &str, prefix: &str, mut req: Request) -> Response {
+    let ip_address = {
+        let workstations = state.workstations.lock().unwrap();
+        workstations.get(workstation_id).map(|w| w.ip_address.clone())
+    };
+
+    let Some(ip_address) = ip_address else {
+        return error_response(StatusCode::NOT_FOUND, format!("Workstation with ID {} not found", workstation_id));
+    };
+
+    state.activity.touch(workstation_id);
+
+    let upstream_addr = format!("{}:{}", ip_address, port);
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+
+    let upstream_uri = match path_and_query.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, format!("Invalid proxy path {}: {}", path_and_query, e)),
+    };
+
+    let upgrade = is_upgrade(&req);
+    let client_upgrade = upgrade.then(|| hyper::upgrade::on(&mut req));
+
+    let original_host = req.headers().get(header::HOST).cloned();
+    let (mut parts, body) = req.into_parts();
+    parts.uri = upstream_uri;
+
+    if !upgrade {
+        for name in HOP_BY_HOP_HEADERS.iter() {
+            parts.headers.remove(name);
+        }
+    }
+
+    if let Ok(host) = HeaderValue::from_str(&upstream_addr) {
+        parts.headers.insert(header::HOST, host);
+    }
+    if let Some(host) = original_host {
+        parts.headers.insert("x-forwarded-host", host);
+    }
+    if let Ok(prefix) = HeaderValue::from_str(prefix) {
+        parts.headers.insert("x-forwarded-prefix", prefix);
+    }
+
+    let stream = match TcpStream::connect(&upstream_addr).await {
+        Ok(stream) => stream,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, format!("Failed to connect to {}: {}", upstream_addr, e)),
+    };
+
+    let (mut sender, connection) = match http1::handshake(TokioIo::new(stream)).await {
+        Ok(handshake) => handshake,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, format!("HTTP handshake with {} failed: {}", upstream_addr, e)),
+    };
+
+    let connection_addr = upstream_addr.clone();
+    tokio::spawn(async move {
+        if let Err(e) = connection.with_upgrades().await {
+            error!("Proxy connection to {} failed: {}", connection_addr, e);
+        }
+    });
+
+    let mut response = match sender.send_request(Request::from_parts(parts, body)).await {
+        Ok(response) => response,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, format!("Proxy request to {} failed: {}", upstream_addr, e)),
+    };
+
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        let Some(client_upgrade) = client_upgrade else {
+            return error_response(StatusCode::BAD_GATEWAY, format!("{} switched protocols without an upgrade request", upstream_addr));
+        };
+
+        let upstream_upgrade = hyper::upgrade::on(&mut response);
+        let in_use = state.activity.open_session(workstation_id);
+
+        tokio::spawn(async move {
+            let _in_use = in_use;
+            let (client, upstream) = match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    error!("Failed to upgrade proxied connection to {}: {}", upstream_addr, e);
+                    return;
+                }
+            };
+
+            info!("Upgraded proxied connection to {}", upstream_addr);
+
+            let mut client = TokioIo::new(client);
+            let mut upstream = TokioIo::new(upstream);
+            if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+                error!("Upgraded proxy connection to {} failed: {}", upstream_addr, e);
+            }
+        });
+
+        return response.map(|_| Body::empty());
+    }
+
+    for name in HOP_BY_HOP_HEADERS.iter() {
+        response.headers_mut().remove(name);
+    }
+
+    response.map(Body::new)
+}
+
+pub async fn proxy_handler(
+    State(state): State<AppState>,
+    Path((workstation_id, port, path)): Path<(String, u16, String)>,
+    req: Request,
+) -> Response {
+    let prefix = format!("/proxy/{}/{}", workstation_id, port);
+    forward(&state, &workstation_id, port, &format!("/{}", path), &prefix, req).await
+}
+
+pub async fn proxy_root_handler(
+    State(state): State<AppState>,
+    Path((workstation_id, port)): Path<(String, u16)>,
+    req: Request,
+) -> Response {
+    let prefix = format!("/proxy/{}/{}", workstation_id, port);
+    forward(&state, &workstation_id, port, "/", &prefix, req).await
+}
+
+// Routes `{port}-{id}.<domain>` requests straight to the guest when a proxy domain is configured.
+pub async fn host_routing_middleware(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(domain) = state.config.proxy_domain.as_deref() else {
+        return next.run(req).await;
+    };
+
+    let host = req.headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(':').next().unwrap_or(value).to_string());
+
+    let Some(host) = host else {
+        return next.run(req).await;
+    };
+
+    let host_pattern = Regex::new(&format!(r"^(\d+)-([0-9a-f-]+)\.{}$", regex::escape(domain))).unwrap();
+
+    let Some(captures) = host_pattern.captures(&host) else {
+        return next.run(req).await;
+    };
+
+    let Ok(port) = captures[1].parse::<u16>() else {
+        return error_response(StatusCode::BAD_REQUEST, format!("Invalid port in host: {}", host));
+    };
+    let workstation_id = captures[2].to_string();
+    let path = req.uri().path().to_string();
+
+    forward(&state, &workstation_id, port, &path, "", req).await
+}
@@ -0,0 +1,70 @@
+use axum::http::StatusCode;
+use russh_sftp::{
+    client::{SftpSession, error::Error as SftpError},
+    protocol::StatusCode as SftpStatusCode,
+};
+use tracing::error;
+
+use crate::ssh::{
+    GuestSession,
+    connect_to_workstation,
+    close_session,
+};
+
+/*----------------------------------------------------------SFTP----------------------------------------------------------*/
+
+pub struct GuestSftp {
+    pub sftp: SftpSession,
+    session: GuestSession,
+}
+
+impl GuestSftp {
+    pub async fn open(ip_address: &str) -> Result<Self, String> {
+        let session = connect_to_workstation(ip_address).await?;
+
+        let channel = match session.channel_open_session().await {
+            Ok(channel) => channel,
+            Err(e) => {
+                let err_msg = format!("Failed to open SSH channel on {}: {}", ip_address, e);
+                error!("{}", err_msg);
+                close_session(&session).await;
+                return Err(err_msg);
+            }
+        };
+
+        if let Err(e) = channel.request_subsystem(true, "sftp").await {
+            let err_msg = format!("Failed to request SFTP subsystem on {}: {}", ip_address, e);
+            error!("{}", err_msg);
+            close_session(&session).await;
+            return Err(err_msg);
+        }
+
+        let sftp = match SftpSession::new(channel.into_stream()).await {
+            Ok(sftp) => sftp,
+            Err(e) => {
+                let err_msg = format!("Failed to start SFTP session on {}: {}", ip_address, e);
+                error!("{}", err_msg);
+                close_session(&session).await;
+                return Err(err_msg);
+            }
+        };
+
+        Ok(GuestSftp { sftp, session })
+    }
+
+    pub async fn close(self) {
+        let _ = self.sftp.close().await;
+        close_session(&self.session).await;
+    }
+}
+
+pub fn sftp_error_status(e: &SftpError) -> StatusCode {
+    match e {
+        SftpError::Status(status) => match status.status_code {
+            SftpStatusCode::NoSuchFile => StatusCode::NOT_FOUND,
+            SftpStatusCode::PermissionDenied => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_GATEWAY,
+        },
+        _ => StatusCode::BAD_GATEWAY,
+    }
+}
@@ -0,0 +1,77 @@
+use russh::{
+    client,
+    keys::PublicKey,
+    Disconnect,
+};
+
+use std::{
+    sync::Arc,
+    time::Duration,
+};
+
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{
+    SSH_USERNAME,
+    SSH_PASSWORD,
+};
+
+/*----------------------------------------------------------SSH CLIENT----------------------------------------------------------*/
+
+pub struct GuestClient;
+
+impl client::Handler for GuestClient {
+    type Error = russh::Error;
+
+    // Workstations are recreated from the same rootfs all the time, so there is no
+    // stable host key to pin. This mirrors StrictHostKeyChecking=no in the terminal.
+    async fn check_server_key(&mut self, _server_public_key: &PublicKey) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
+pub type GuestSession = client::Handle<GuestClient>;
+
+pub async fn connect_to_workstation(ip_address: &str) -> Result<GuestSession, String> {
+    info!("Opening SSH session to workstation: {}", ip_address);
+
+    let config = Arc::new(client::Config {
+        inactivity_timeout: None,
+        keepalive_interval: Some(Duration::from_secs(30)),
+        keepalive_max: 3,
+        ..Default::default()
+    });
+
+    let connect = client::connect(config, (ip_address, 22), GuestClient);
+
+    let mut session = match tokio::time::timeout(Duration::from_secs(10), connect).await {
+        Ok(Ok(session)) => session,
+        Ok(Err(e)) => {
+            let err_msg = format!("Failed to connect to workstation {} over SSH: {}", ip_address, e);
+            error!("{}", err_msg);
+            return Err(err_msg);
+        },
+        Err(_) => {
+            let err_msg = format!("Timed out connecting to workstation {} over SSH", ip_address);
+            error!("{}", err_msg);
+            return Err(err_msg);
+        }
+    };
+
+    let auth = session.authenticate_password(SSH_USERNAME, SSH_PASSWORD).await;
+
+    if auth.is_err() || !auth.unwrap().success() {
+        let err_msg = format!("SSH authentication failed for workstation: {}", ip_address);
+        error!("{}", err_msg);
+        return Err(err_msg);
+    }
+
+    Ok(session)
+}
+
+pub async fn close_session(session: &GuestSession) {
+    let _ = session.disconnect(Disconnect::ByApplication, "", "en").await;
+}
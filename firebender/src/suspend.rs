@@ -0,0 +1,197 @@
+use std::{
+    fs,
+    time::Duration,
+};
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{
+    AppState,
+    Workstation,
+    WorkstationStatus,
+};
+use crate::helpers::{
+    configure_logging,
+    firecracker_api,
+    firecracker_socket_path,
+    kill_firecracker,
+    snapshot_mem_path,
+    snapshot_state_path,
+    spawn_firecracker_process,
+};
+use crate::affinity::pin_threads;
+use crate::jailer::{
+    api_path,
+    create_vm_file,
+};
+
+/*----------------------------------------------------------SNAPSHOTS----------------------------------------------------------*/
+
+const RESUME_WAIT_SECS: u64 = 60;
+
+fn suspend_vm(workstation: &Workstation) -> Result<(), String> {
+    let vm_id = workstation.vm_id.as_str();
+    let socket_path = firecracker_socket_path(workstation);
+    let state_path = snapshot_state_path(workstation);
+    let mem_path = snapshot_mem_path(workstation);
+
+    info!("Suspending VM ID: {}", vm_id);
+
+    // A jailed VMM cannot create files in its chroot, so the snapshot files are handed to it up front.
+    if workstation.jail_uid.is_some() {
+        create_vm_file(workstation, &state_path)?;
+        create_vm_file(workstation, &mem_path)?;
+    }
+
+    firecracker_api(&socket_path, "PATCH", "/vm", r#"{"state": "Paused"}"#)?;
+
+    let create_snapshot = format!(
+        r#"{{"snapshot_type": "Full", "snapshot_path": "{}", "mem_file_path": "{}"}}"#,
+        api_path(workstation, &state_path), api_path(workstation, &mem_path)
+    );
+
+    if let Err(e) = firecracker_api(&socket_path, "PUT", "/snapshot/create", &create_snapshot) {
+        let _ = firecracker_api(&socket_path, "PATCH", "/vm", r#"{"state": "Resumed"}"#);
+        return Err(e);
+    }
+
+    // The snapshot is on disk but the VM could not be stopped, so it carries on running instead.
+    if let Err(e) = kill_firecracker(workstation) {
+        let _ = firecracker_api(&socket_path, "PATCH", "/vm", r#"{"state": "Resumed"}"#);
+        remove_snapshot(workstation);
+        return Err(e);
+    }
+
+    info!("VM ID: {} suspended to {}", vm_id, state_path);
+
+    Ok(())
+}
+
+fn resume_vm(workstation: &Workstation) -> Result<(), String> {
+    let vm_id = workstation.vm_id.as_str();
+
+    info!("Resuming VM ID: {}", vm_id);
+
+    let socket_path = spawn_firecracker_process(workstation)?;
+
+    // The VM only runs once everything else is in place, so a failed attempt can be thrown away and the
+    // untouched snapshot resumed again later.
+    let restore = || -> Result<(), String> {
+        configure_logging(&socket_path, workstation)?;
+
+        let load_snapshot = format!(
+            r#"{{"snapshot_path": "{}", "mem_backend": {{"backend_type": "File", "backend_path": "{}"}}, "resume_vm": false}}"#,
+            api_path(workstation, &snapshot_state_path(workstation)), api_path(workstation, &snapshot_mem_path(workstation))
+        );
+
+        firecracker_api(&socket_path, "PUT", "/snapshot/load", &load_snapshot)?;
+
+        pin_threads(workstation)?;
+
+        firecracker_api(&socket_path, "PATCH", "/vm", r#"{"state": "Resumed"}"#)
+    };
+
+    if let Err(e) = restore() {
+        if let Err(kill_error) = kill_firecracker(workstation) {
+            error!("{}", kill_error);
+        }
+        return Err(e);
+    }
+
+    remove_snapshot(workstation);
+
+    info!("VM ID: {} resumed", vm_id);
+
+    Ok(())
+}
+
+fn remove_snapshot(workstation: &Workstation) {
+    let _ = fs::remove_file(snapshot_state_path(workstation));
+    let _ = fs::remove_file(snapshot_mem_path(workstation));
+}
+
+/*----------------------------------------------------------LIFECYCLE----------------------------------------------------------*/
+
+fn set_status(state: &AppState, workstation_id: &str, status: WorkstationStatus) {
+    if let Some(workstation) = state.workstations.lock().unwrap().get_mut(workstation_id) {
+        workstation.status = status;
+    }
+}
+
+pub async fn suspend_workstation(state: &AppState, workstation_id: &str) -> Result<(), String> {
+    let workstation = {
+        let mut workstations = state.workstations.lock().unwrap();
+        match workstations.get_mut(workstation_id) {
+            Some(workstation) if workstation.status == WorkstationStatus::Running => {
+                workstation.status = WorkstationStatus::Suspending;
+                workstation.clone()
+            },
+            Some(_) => return Ok(()),
+            None => return Err(format!("Workstation with ID {} not found", workstation_id)),
+        }
+    };
+
+    let result = tokio::task::spawn_blocking(move || suspend_vm(&workstation))
+        .await
+        .unwrap_or_else(|e| Err(format!("Suspend task panicked: {}", e)));
+
+    match result {
+        Ok(()) => {
+            set_status(state, workstation_id, WorkstationStatus::Suspended);
+            Ok(())
+        },
+        Err(e) => {
+            set_status(state, workstation_id, WorkstationStatus::Running);
+            Err(e)
+        }
+    }
+}
+
+// Makes sure the workstation is running, restoring it from its snapshot if it was suspended.
+pub async fn ensure_running(state: &AppState, workstation_id: &str) -> Result<(), String> {
+    let mut waited = Duration::ZERO;
+
+    let workstation = loop {
+        {
+            let mut workstations = state.workstations.lock().unwrap();
+            let Some(workstation) = workstations.get_mut(workstation_id) else {
+                return Err(format!("Workstation with ID {} not found", workstation_id));
+            };
+
+            match workstation.status {
+                WorkstationStatus::Running => return Ok(()),
+                WorkstationStatus::Suspended => {
+                    workstation.status = WorkstationStatus::Resuming;
+                    break workstation.clone();
+                },
+                WorkstationStatus::Suspending | WorkstationStatus::Resuming => {},
+            }
+        }
+
+        if waited >= Duration::from_secs(RESUME_WAIT_SECS) {
+            return Err(format!("Timed out waiting for workstation {} to settle", workstation_id));
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        waited += Duration::from_millis(500);
+    };
+
+    let result = tokio::task::spawn_blocking(move || resume_vm(&workstation))
+        .await
+        .unwrap_or_else(|e| Err(format!("Resume task panicked: {}", e)));
+
+    match result {
+        Ok(()) => {
+            set_status(state, workstation_id, WorkstationStatus::Running);
+            state.activity.touch(workstation_id);
+            Ok(())
+        },
+        Err(e) => {
+            error!("Failed to resume workstation {}: {}", workstation_id, e);
+            set_status(state, workstation_id, WorkstationStatus::Suspended);
+            Err(e)
+        }
+    }
+}
@@ -3,11 +3,12 @@ use axum::{
 };
 use futures::{StreamExt, SinkExt};
 use crate::AppState;
+use crate::suspend::ensure_running;
 use tracing::{info, error};
 use tokio::process::Command;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use std::sync::Arc;
-use tokio::sync::Mutex as TokioMutex;
+use tokio::sync::{broadcast, Mutex as TokioMutex};
 use tokio::time::{sleep, Duration};
 
 pub async fn terminal_ws_handler(
@@ -17,19 +18,26 @@ pub async fn terminal_ws_handler(
 ) {
     info!("Websocket connection has been made, VM ID: {}", id);
 
+    let _session = state.metrics.track_terminal_session();
+
+    let mut ws = ws;
+    if let Err(e) = ensure_running(&state, &id).await {
+        error!("{}", e);
+        if let Err(e) = ws.send(Message::Text(format!("Error: {}", e).into())).await {
+            error!("WebSocket error: {}", e);
+        }
+        return;
+    }
+
     let ip_address = {
         let workstations = state.workstations.lock().unwrap();
-        match workstations.get(&id) {
-            Some(w) => Some(w.ip_address.clone()),
-            None => None,
-        }
+        workstations.get(&id).map(|w| w.ip_address.clone())
     };
 
     let ip_address = match ip_address {
         Some(ip) => ip,
         None => {
             error!("Workstation not found: {}", id);
-            let mut ws = ws;
             if let Err(e) = ws.send(Message::Text("Error: VM not found".to_string().into())).await {
                 error!("WebSocket error: {}", e);
             }
@@ -38,6 +46,8 @@ pub async fn terminal_ws_handler(
     };
     
     info!("VM IP address: {}", ip_address);
+
+    let _in_use = state.activity.open_session(&id);
     
     let (mut ws_sender, mut ws_receiver) = ws.split();
     
@@ -157,6 +167,8 @@ pub async fn terminal_ws_handler(
 
     // 3. WebSocket -> SSH stdin sender
     let stdin_clone = Arc::clone(&stdin);
+    let activity_state = state.clone();
+    let activity_id = id.clone();
     let ws_to_ssh_task = tokio::spawn(async move {
 
         sleep(Duration::from_secs(1)).await;
@@ -166,6 +178,7 @@ pub async fn terminal_ws_handler(
                 Ok(Message::Text(text)) => {
                     let command_str = text.to_string();
                     info!("WebSocket command received: {:?}", command_str);
+                    activity_state.activity.touch(&activity_id);
                     
                     let mut stdin = stdin_clone.lock().await;
                     if let Err(e) = stdin.write_all(command_str.as_bytes()).await {
@@ -192,6 +205,29 @@ pub async fn terminal_ws_handler(
         info!("WebSocket -> SSH task completed");
     });
 
+    // 4. Expiry notices -> WebSocket sender
+    let ws_sender_clone = Arc::clone(&ws_sender);
+    let mut notices = state.activity.subscribe();
+    let notice_task = tokio::spawn(async move {
+        loop {
+            let (workstation_id, notice) = match notices.recv().await {
+                Ok(notice) => notice,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            if workstation_id != id {
+                continue;
+            }
+
+            let mut sender = ws_sender_clone.lock().await;
+            if let Err(e) = sender.send(Message::Text(notice.into())).await {
+                error!("WebSocket notice sending error: {}", e);
+                break;
+            }
+        }
+    });
+
     tokio::select! {
         _ = stdout_task => {
             info!("SSH stdout reader task completed");
@@ -204,6 +240,8 @@ pub async fn terminal_ws_handler(
         }
     }
     
+    notice_task.abort();
+
     info!("Terminal session terminated, SSH process is being closed");
     let _ = ssh_process.kill().await;
 }
\ No newline at end of file
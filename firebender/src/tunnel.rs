@@ -0,0 +1,192 @@
+use axum::{
+    extract::{ws::{WebSocket, Message}, State, Path},
+};
+use futures::{StreamExt, SinkExt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::Message as ClientMessage,
+};
+use tracing::{info, error};
+
+use crate::AppState;
+
+/*----------------------------------------------------------SERVER----------------------------------------------------------*/
+
+pub async fn tunnel_ws_handler(
+    ws: WebSocket,
+    Path((id, port)): Path<(String, u16)>,
+    State(state): State<AppState>,
+) {
+    info!("Tunnel websocket connection has been made, VM ID: {}, port: {}", id, port);
+
+    let ip_address = {
+        let workstations = state.workstations.lock().unwrap();
+        workstations.get(&id).map(|w| w.ip_address.clone())
+    };
+
+    let mut ws = ws;
+
+    let Some(ip_address) = ip_address else {
+        error!("Workstation not found: {}", id);
+        let _ = ws.send(Message::Close(None)).await;
+        return;
+    };
+
+    let guest_addr = format!("{}:{}", ip_address, port);
+    let stream = match TcpStream::connect(&guest_addr).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Failed to connect tunnel to {}: {}", guest_addr, e);
+            let _ = ws.send(Message::Close(None)).await;
+            return;
+        }
+    };
+
+    let _in_use = state.activity.open_session(&id);
+
+    let (mut tcp_reader, mut tcp_writer) = stream.into_split();
+    let (mut ws_sender, mut ws_receiver) = ws.split();
+
+    // 1. Guest TCP -> WebSocket sender
+    let tcp_to_ws_task = tokio::spawn(async move {
+        let mut buffer = vec![0; 16384];
+        loop {
+            match tcp_reader.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => {
+                    if let Err(e) = ws_sender.send(Message::Binary(buffer[..n].to_vec().into())).await {
+                        error!("WebSocket data sending error: {}", e);
+                        break;
+                    }
+                },
+                Err(e) => {
+                    error!("Tunnel read error: {}", e);
+                    break;
+                }
+            }
+        }
+        let _ = ws_sender.send(Message::Close(None)).await;
+    });
+
+    // 2. WebSocket -> Guest TCP
+    let ws_to_tcp_task = tokio::spawn(async move {
+        state.activity.touch(&id);
+
+        while let Some(result) = ws_receiver.next().await {
+            let data = match result {
+                Ok(Message::Binary(data)) => data.to_vec(),
+                Ok(Message::Text(text)) => text.as_bytes().to_vec(),
+                Ok(Message::Close(_)) => break,
+                Err(e) => {
+                    error!("WebSocket error: {}", e);
+                    break;
+                },
+                _ => continue,
+            };
+
+            state.activity.touch(&id);
+
+            if let Err(e) = tcp_writer.write_all(&data).await {
+                error!("Tunnel write error: {}", e);
+                break;
+            }
+        }
+        let _ = tcp_writer.shutdown().await;
+    });
+
+    tokio::select! {
+        _ = tcp_to_ws_task => {},
+        _ = ws_to_tcp_task => {},
+    }
+
+    info!("Tunnel to {} closed", guest_addr);
+}
+
+/*----------------------------------------------------------CLIENT----------------------------------------------------------*/
+
+async fn relay_local_connection(stream: TcpStream, url: String) {
+    let (ws, _) = match connect_async(url.as_str()).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("Failed to open tunnel {}: {}", url, e);
+            return;
+        }
+    };
+
+    let (mut tcp_reader, mut tcp_writer) = stream.into_split();
+    let (mut ws_sender, mut ws_receiver) = ws.split();
+
+    let tcp_to_ws = async {
+        let mut buffer = vec![0; 16384];
+        loop {
+            match tcp_reader.read(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if ws_sender.send(ClientMessage::Binary(buffer[..n].to_vec().into())).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = ws_sender.send(ClientMessage::Close(None)).await;
+    };
+
+    let ws_to_tcp = async {
+        while let Some(Ok(message)) = ws_receiver.next().await {
+            let data = match message {
+                ClientMessage::Binary(data) => data.to_vec(),
+                ClientMessage::Text(text) => text.as_bytes().to_vec(),
+                ClientMessage::Close(_) => break,
+                _ => continue,
+            };
+
+            if tcp_writer.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+        let _ = tcp_writer.shutdown().await;
+    };
+
+    tokio::select! {
+        _ = tcp_to_ws => {},
+        _ = ws_to_tcp => {},
+    }
+}
+
+pub async fn run_tunnel_client(server: &str, workstation_id: &str, remote_port: u16, local_addr: &str) -> Result<(), String> {
+    let url = format!("{}/ws/workstations/{}/tunnel/{}", server.trim_end_matches('/'), workstation_id, remote_port);
+
+    let listener = TcpListener::bind(local_addr).await
+        .map_err(|e| format!("Failed to listen on {}: {}", local_addr, e))?;
+
+    info!("Forwarding {} -> workstation {} port {} via {}", local_addr, workstation_id, remote_port, server);
+
+    loop {
+        let (stream, peer) = listener.accept().await
+            .map_err(|e| format!("Failed to accept connection on {}: {}", local_addr, e))?;
+
+        info!("Tunnel connection from {}", peer);
+        tokio::spawn(relay_local_connection(stream, url.clone()));
+    }
+}
+
+pub async fn tunnel_command(args: &[String]) -> Result<(), String> {
+    let usage = "Usage: firebender tunnel <server-url> <workstation-id> <remote-port> [local-address]";
+
+    let (Some(server), Some(workstation_id), Some(remote_port)) = (args.first(), args.get(1), args.get(2)) else {
+        return Err(usage.to_string());
+    };
+
+    let remote_port = remote_port.parse::<u16>()
+        .map_err(|_| format!("Invalid remote port: {}\n{}", remote_port, usage))?;
+
+    let local_addr = args.get(3)
+        .cloned()
+        .unwrap_or_else(|| format!("127.0.0.1:{}", remote_port));
+
+    run_tunnel_client(server, workstation_id, remote_port, &local_addr).await
+}
@@ -0,0 +1,184 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fs,
+    os::unix::fs::MetadataExt,
+    time::Duration,
+};
+use tracing::info;
+
+use crate::{AppState, Workstation};
+use crate::helpers::{
+    firecracker_pid,
+    rootfs_path,
+    tap_name,
+    unix_now,
+};
+use crate::jailer::veth_name;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const SAMPLE_INTERVAL_SECS: u64 = 10;
+const HISTORY_LENGTH: usize = 60;
+
+// Kernel clock ticks per second used by /proc/<pid>/stat; 100 on every mainstream Linux build.
+const CLOCK_TICKS_PER_SEC: f64 = 100.0;
+
+#[derive(Serialize, Clone)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub cpu_time_secs: f64,
+    pub rss_bytes: u64,
+    pub threads: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct NetworkUsage {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct DiskUsage {
+    pub allocated_bytes: u64,
+    pub used_bytes: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct UsageSample {
+    pub timestamp: u64,
+    pub process: Option<ProcessUsage>,
+    pub network: Option<NetworkUsage>,
+    pub disk: Option<DiskUsage>,
+}
+
+pub type UsageHistory = VecDeque<UsageSample>;
+
+#[derive(Serialize)]
+struct UsageResponse {
+    current: UsageSample,
+    history: Vec<UsageSample>,
+}
+
+/*----------------------------------------------------------SAMPLING----------------------------------------------------------*/
+
+fn process_usage(pid: u32) -> Option<ProcessUsage> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+
+    // Fields after the parenthesised command name start at `state` (field 3), so utime and stime are 11 and 12.
+    let fields = stat.rsplit_once(')')?.1.split_whitespace().collect::<Vec<_>>();
+    let utime = fields.get(11)?.parse::<u64>().ok()?;
+    let stime = fields.get(12)?.parse::<u64>().ok()?;
+
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let status_value = |key: &str| {
+        status.lines()
+            .find_map(|line| line.strip_prefix(key))
+            .and_then(|value| value.split_whitespace().next())
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+
+    Some(ProcessUsage {
+        pid,
+        cpu_time_secs: (utime + stime) as f64 / CLOCK_TICKS_PER_SEC,
+        rss_bytes: status_value("VmRSS:") * 1024,
+        threads: status_value("Threads:"),
+    })
+}
+
+fn network_usage(tap: &str) -> Option<NetworkUsage> {
+    let counter = |name: &str| {
+        fs::read_to_string(format!("/sys/class/net/{}/statistics/{}", tap, name))
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    Some(NetworkUsage {
+        rx_bytes: counter("rx_bytes")?,
+        tx_bytes: counter("tx_bytes")?,
+        rx_packets: counter("rx_packets")?,
+        tx_packets: counter("tx_packets")?,
+    })
+}
+
+fn disk_usage(path: &str) -> Option<DiskUsage> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some(DiskUsage {
+        allocated_bytes: metadata.len(),
+        used_bytes: metadata.blocks() * 512,
+    })
+}
+
+pub fn sample_usage(workstation: &Workstation) -> UsageSample {
+    UsageSample {
+        timestamp: unix_now(),
+        process: firecracker_pid(workstation).and_then(process_usage),
+        network: match workstation.jail_uid {
+            Some(_) => network_usage(&veth_name(workstation.order)),
+            None => network_usage(&tap_name(workstation.order)),
+        },
+        disk: disk_usage(&rootfs_path(workstation)),
+    }
+}
+
+pub async fn collect_usage(state: AppState) {
+    info!("Sampling workstation usage every {} seconds", SAMPLE_INTERVAL_SECS);
+
+    let mut interval = tokio::time::interval(Duration::from_secs(SAMPLE_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let workstations = state.workstations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+        let samples = workstations.iter()
+            .map(|w| (w.id.clone(), sample_usage(w)))
+            .collect::<Vec<_>>();
+
+        let mut usage = state.usage.lock().unwrap();
+        usage.retain(|id, _| workstations.iter().any(|w| &w.id == id));
+
+        for (id, sample) in samples {
+            let history = usage.entry(id).or_default();
+            if history.len() == HISTORY_LENGTH {
+                history.pop_front();
+            }
+            history.push_back(sample);
+        }
+    }
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn usage_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+) -> impl IntoResponse {
+    info!("Usage request received for ID: {}", workstation_id);
+
+    let workstation = state.workstations.lock().unwrap().get(&workstation_id).cloned();
+
+    let Some(workstation) = workstation else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    };
+
+    let history = state.usage.lock().unwrap()
+        .get(&workstation_id)
+        .map(|history| history.iter().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let response = UsageResponse {
+        current: sample_usage(&workstation),
+        history,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
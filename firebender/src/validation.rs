@@ -0,0 +1,330 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    CreateWorkstationPayload,
+    Workstation,
+};
+use crate::limits::{
+    RateLimit,
+    UpdateWorkstationPayload,
+};
+use crate::config::Config;
+use crate::cgroup::MAX_CPU_WEIGHT;
+use crate::affinity::{
+    online_cpu_list,
+    parse_cpu_list,
+};
+use crate::capacity::{
+    BYTES_PER_GIB,
+    rootfs_image_bytes,
+};
+use crate::cpu_template::{
+    STATIC_CPU_TEMPLATES,
+    find_cpu_template,
+};
+use crate::gateway::parse_public_key;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+// Firecracker refuses machine configs with more vCPUs than this.
+pub const FIRECRACKER_MAX_VCPUS: u64 = 32;
+
+// Rate limiter token buckets are sized in bytes: 1 Mbps = 125000 bytes per second.
+pub const BYTES_PER_MBIT: u64 = 125000;
+
+#[derive(Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub constraint: String,
+    pub value: Value,
+}
+
+impl FieldError {
+    fn new(field: &str, constraint: String, value: impl Into<Value>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            constraint,
+            value: value.into(),
+        }
+    }
+}
+
+/*----------------------------------------------------------VALIDATION----------------------------------------------------------*/
+
+// Network buckets are capped at the bandwidth maximum only; drive buckets also have an IOPS maximum.
+fn validate_rate_limit(field: &str, limit: &RateLimit, max_bandwidth_mbps: u64, max_ops: Option<u64>, errors: &mut Vec<FieldError>) {
+    if limit.bandwidth_mbps > max_bandwidth_mbps {
+        errors.push(FieldError::new(
+            &format!("{}.bandwidth_mbps", field),
+            format!("must be at most {} Mbps", max_bandwidth_mbps),
+            limit.bandwidth_mbps,
+        ));
+    }
+
+    if let Some(max_ops) = max_ops
+        && limit.ops_per_sec > max_ops
+    {
+        errors.push(FieldError::new(&format!("{}.ops_per_sec", field), format!("must be at most {}", max_ops), limit.ops_per_sec));
+    }
+
+    if limit.burst_mbit.checked_mul(BYTES_PER_MBIT).is_none() {
+        errors.push(FieldError::new(&format!("{}.burst_mbit", field), "is too large".to_string(), limit.burst_mbit));
+    }
+
+    // Firecracker treats a bucket of size 0 as disabled and would silently drop the burst.
+    if limit.burst_mbit > 0 && limit.bandwidth_mbps == 0 {
+        errors.push(FieldError::new(&format!("{}.burst_mbit", field), "requires a non-zero bandwidth_mbps".to_string(), limit.burst_mbit));
+    }
+
+    if limit.ops_burst > 0 && limit.ops_per_sec == 0 {
+        errors.push(FieldError::new(&format!("{}.ops_burst", field), "requires a non-zero ops_per_sec".to_string(), limit.ops_burst));
+    }
+}
+
+// `prefix` is empty for the root drive and `volumes[i].` for an attached volume.
+fn validate_disk_limits(config: &Config, prefix: &str, bandwidth_mbps: Option<u64>, iops: Option<u64>, errors: &mut Vec<FieldError>) {
+    if let Some(bandwidth_mbps) = bandwidth_mbps
+        && bandwidth_mbps > config.max_disk_bandwidth_mbps
+    {
+        errors.push(FieldError::new(
+            &format!("{}disk_bandwidth_mbps", prefix),
+            format!("must be at most {} Mbps", config.max_disk_bandwidth_mbps),
+            bandwidth_mbps,
+        ));
+    }
+
+    if let Some(iops) = iops
+        && iops > config.max_disk_iops
+    {
+        errors.push(FieldError::new(&format!("{}disk_iops", prefix), format!("must be at most {}", config.max_disk_iops), iops));
+    }
+}
+
+pub fn validate_update_payload(config: &Config, workstation: &Workstation, payload: &UpdateWorkstationPayload) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+
+    if let Some(bandwidth) = payload.bandwidth
+        && (bandwidth > config.max_bandwidth_mbps || bandwidth.checked_mul(BYTES_PER_MBIT).is_none())
+    {
+        errors.push(FieldError::new("bandwidth", format!("must be at most {} Mbps", config.max_bandwidth_mbps), bandwidth));
+    }
+
+    for (field, limit) in [
+        ("rx_rate_limit", &payload.rx_rate_limit),
+        ("tx_rate_limit", &payload.tx_rate_limit),
+    ] {
+        if let Some(limit) = limit {
+            validate_rate_limit(field, limit, config.max_bandwidth_mbps, None, &mut errors);
+        }
+    }
+
+    if let Some(limit) = &payload.disk_rate_limit {
+        validate_rate_limit("disk_rate_limit", limit, config.max_disk_bandwidth_mbps, Some(config.max_disk_iops), &mut errors);
+    }
+
+    if let Some(target_mib) = payload.balloon_target_mib {
+        if workstation.balloon.is_none() {
+            errors.push(FieldError::new("balloon_target_mib", "needs a workstation created with a balloon".to_string(), target_mib));
+        } else if target_mib > workstation.mem_size_mib {
+            errors.push(FieldError::new("balloon_target_mib", format!("must be at most {} MiB", workstation.mem_size_mib), target_mib));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn validate_create_payload(config: &Config, payload: &CreateWorkstationPayload) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+
+    let max_vcpus = config.max_vcpus.min(FIRECRACKER_MAX_VCPUS);
+    if payload.vcpu_count == 0 || payload.vcpu_count > max_vcpus {
+        errors.push(FieldError::new("vcpu_count", format!("must be between 1 and {}", max_vcpus), payload.vcpu_count));
+    } else if payload.smt_enabled && payload.vcpu_count > 1 && !payload.vcpu_count.is_multiple_of(2) {
+        errors.push(FieldError::new("vcpu_count", "must be 1 or even when smt_enabled is true".to_string(), payload.vcpu_count));
+    }
+
+    if payload.mem_size_mib < config.min_mem_size_mib || payload.mem_size_mib > config.max_mem_size_mib {
+        errors.push(FieldError::new(
+            "mem_size_mib",
+            format!("must be between {} and {}", config.min_mem_size_mib, config.max_mem_size_mib),
+            payload.mem_size_mib,
+        ));
+    }
+
+    if payload.bandwidth > config.max_bandwidth_mbps || payload.bandwidth.checked_mul(BYTES_PER_MBIT).is_none() {
+        errors.push(FieldError::new("bandwidth", format!("must be at most {} Mbps", config.max_bandwidth_mbps), payload.bandwidth));
+    }
+
+    validate_disk_limits(config, "", payload.disk_bandwidth_mbps, payload.disk_iops, &mut errors);
+
+    if let Some(disk_size_gib) = payload.disk_size_gib {
+        let min_disk_size_gib = rootfs_image_bytes().div_ceil(BYTES_PER_GIB).max(1);
+
+        if payload.read_only {
+            errors.push(FieldError::new("disk_size_gib", "needs a writable workstation".to_string(), disk_size_gib));
+        } else if disk_size_gib < min_disk_size_gib || disk_size_gib > config.max_disk_size_gib {
+            errors.push(FieldError::new(
+                "disk_size_gib",
+                format!("must be between {} and {}", min_disk_size_gib, config.max_disk_size_gib),
+                disk_size_gib,
+            ));
+        }
+    }
+
+    if let Some(cpu_shares) = payload.cpu_shares
+        && (cpu_shares == 0 || cpu_shares > MAX_CPU_WEIGHT)
+    {
+        errors.push(FieldError::new("cpu_shares", format!("must be between 1 and {}", MAX_CPU_WEIGHT), cpu_shares));
+    }
+
+    if let Some(affinity) = &payload.cpu_affinity {
+        if affinity.vcpus.len() as u64 != payload.vcpu_count {
+            errors.push(FieldError::new("cpu_affinity.vcpus", format!("must list one core per vCPU ({})", payload.vcpu_count), affinity.vcpus.clone()));
+        }
+
+        let online_list = online_cpu_list();
+        let online = parse_cpu_list(&online_list).unwrap_or_default();
+        let mut seen = Vec::new();
+        for core in affinity.cores() {
+            if !online.contains(&core) {
+                errors.push(FieldError::new("cpu_affinity", format!("cores must be online host CPUs ({})", online_list), core));
+            } else if seen.contains(&core) {
+                errors.push(FieldError::new("cpu_affinity", "cores must not repeat".to_string(), core));
+            }
+            seen.push(core);
+        }
+    }
+
+    if let Some(template) = &payload.cpu_template
+        && find_cpu_template(template).is_none()
+    {
+        errors.push(FieldError::new(
+            "cpu_template",
+            format!("must be one of {} or an uploaded custom template", STATIC_CPU_TEMPLATES.join(", ")),
+            template.as_str(),
+        ));
+    }
+
+    if let Some(balloon) = &payload.balloon
+        && balloon.amount_mib > payload.mem_size_mib
+    {
+        errors.push(FieldError::new("balloon.amount_mib", "must not exceed mem_size_mib".to_string(), balloon.amount_mib));
+    }
+
+    for (i, attachment) in payload.volumes.iter().enumerate() {
+        if payload.volumes[..i].iter().any(|other| other.id == attachment.id) {
+            errors.push(FieldError::new(&format!("volumes[{}].id", i), "must not repeat".to_string(), attachment.id.as_str()));
+        }
+
+        validate_disk_limits(config, &format!("volumes[{}].", i), attachment.disk_bandwidth_mbps, attachment.disk_iops, &mut errors);
+    }
+
+    if payload.ttl == Some(0) {
+        errors.push(FieldError::new("ttl", "must be greater than 0 seconds".to_string(), 0));
+    }
+
+    if payload.idle_timeout == Some(0) {
+        errors.push(FieldError::new("idle_timeout", "must be greater than 0 seconds".to_string(), 0));
+    }
+
+    for (i, key) in payload.ssh_public_keys.iter().enumerate() {
+        if parse_public_key(key).is_err() {
+            errors.push(FieldError::new(&format!("ssh_public_keys[{}]", i), "must be an OpenSSH public key".to_string(), key.as_str()));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volumes::VolumeAttachment;
+
+    fn payload() -> CreateWorkstationPayload {
+        CreateWorkstationPayload {
+            vcpu_count: 2,
+            mem_size_mib: 1024,
+            ..Default::default()
+        }
+    }
+
+    fn invalid_fields(payload: &CreateWorkstationPayload) -> Vec<String> {
+        match validate_create_payload(&Config::defaults(), payload) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| error.field).collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_a_plain_workstation() {
+        assert!(invalid_fields(&payload()).is_empty());
+    }
+
+    #[test]
+    fn rejects_vcpu_counts_out_of_range() {
+        assert_eq!(invalid_fields(&CreateWorkstationPayload { vcpu_count: 0, ..payload() }), ["vcpu_count"]);
+        assert_eq!(invalid_fields(&CreateWorkstationPayload { vcpu_count: FIRECRACKER_MAX_VCPUS + 1, ..payload() }), ["vcpu_count"]);
+    }
+
+    #[test]
+    fn smt_needs_an_even_vcpu_count() {
+        assert_eq!(invalid_fields(&CreateWorkstationPayload { vcpu_count: 3, smt_enabled: true, ..payload() }), ["vcpu_count"]);
+        assert!(invalid_fields(&CreateWorkstationPayload { vcpu_count: 1, smt_enabled: true, ..payload() }).is_empty());
+    }
+
+    #[test]
+    fn reports_every_invalid_field() {
+        let payload = CreateWorkstationPayload {
+            mem_size_mib: 1,
+            bandwidth: u64::MAX,
+            ttl: Some(0),
+            ssh_public_keys: vec!["not a key".to_string()],
+            ..payload()
+        };
+
+        assert_eq!(invalid_fields(&payload), ["mem_size_mib", "bandwidth", "ttl", "ssh_public_keys[0]"]);
+    }
+
+    #[test]
+    fn bursts_need_a_rate() {
+        let config = Config::defaults();
+        let fields = |limit: RateLimit| {
+            let mut errors = Vec::new();
+            validate_rate_limit("rx_rate_limit", &limit, config.max_bandwidth_mbps, None, &mut errors);
+            errors.into_iter().map(|error| error.field).collect::<Vec<_>>()
+        };
+
+        assert_eq!(fields(RateLimit { burst_mbit: 10, ops_burst: 5, ..Default::default() }), ["rx_rate_limit.burst_mbit", "rx_rate_limit.ops_burst"]);
+        assert!(fields(RateLimit { bandwidth_mbps: 10, burst_mbit: 10, ops_per_sec: 100, ops_burst: 5 }).is_empty());
+    }
+
+    #[test]
+    fn disk_limits_have_their_own_maximum() {
+        let config = Config::defaults();
+        let volume = VolumeAttachment {
+            id: "data".to_string(),
+            read_only: false,
+            disk_bandwidth_mbps: Some(config.max_disk_bandwidth_mbps),
+            disk_iops: Some(config.max_disk_iops + 1),
+            rate_limit: RateLimit::default(),
+        };
+        let payload = CreateWorkstationPayload {
+            disk_bandwidth_mbps: Some(config.max_disk_bandwidth_mbps + 1),
+            disk_iops: Some(config.max_disk_iops),
+            volumes: vec![volume],
+            ..payload()
+        };
+
+        assert_eq!(invalid_fields(&payload), ["disk_bandwidth_mbps", "volumes[0].disk_iops"]);
+    }
+}
@@ -0,0 +1,232 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    time::Duration,
+};
+use tokio::process::Command;
+use tracing::{
+    info,
+    error,
+};
+
+use crate::{AppState, Workstation, WorkstationStatus};
+use crate::helpers::{
+    firecracker_socket_path,
+    unix_now,
+    vmm_metrics_path,
+};
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+const COLLECT_INTERVAL_SECS: u64 = 15;
+
+// Firecracker metric groups worth keeping: vCPU exits, block and net device counters
+// (including the per-device `block_<drive>` / `net_<iface>` groups and their rate limiter throttling).
+const METRIC_GROUPS: [&str; 3] = ["vcpu", "block", "net"];
+
+#[derive(Serialize, Clone, Default)]
+pub struct VmmMetrics {
+    #[serde(skip)]
+    offset: u64,
+    pub counters: BTreeMap<String, u64>,
+    pub gauges: BTreeMap<String, u64>,
+    pub updated_at: Option<u64>,
+}
+
+/*----------------------------------------------------------COLLECTOR----------------------------------------------------------*/
+
+async fn flush_metrics(workstation: &Workstation) -> Result<(), String> {
+    let vm_id = workstation.vm_id.as_str();
+    let socket_path = firecracker_socket_path(workstation);
+
+    let status = Command::new("curl")
+        .args(["-s", "--unix-socket", &socket_path, "-X", "PUT", "http://localhost/actions", "-d", r#"{"action_type": "FlushMetrics"}"#, "-H", "Content-Type: application/json"])
+        .status()
+        .await
+        .map_err(|e| format!("Failed to flush metrics for VM ID {}: {}", vm_id, e))?;
+
+    if !status.success() {
+        return Err(format!("Failed to flush metrics for VM ID: {}", vm_id));
+    }
+
+    Ok(())
+}
+
+// Firecracker writes most fields as counter deltas since the previous flush, and those are added onto the
+// running totals. Latency aggregates (`{"min_us", "max_us", "sum_us"}` objects) and other `_us` values only
+// describe the last flush period, so they are kept as the latest value instead.
+fn apply_line(metrics: &mut VmmMetrics, line: &str) {
+    let Ok(Value::Object(groups)) = serde_json::from_str::<Value>(line) else {
+        return;
+    };
+
+    for (group, fields) in groups {
+        if !METRIC_GROUPS.iter().any(|prefix| group.starts_with(prefix)) {
+            continue;
+        }
+        let Value::Object(fields) = fields else {
+            continue;
+        };
+
+        for (field, value) in fields {
+            let name = format!("{}.{}", group, field);
+
+            match value {
+                Value::Object(aggregate) => {
+                    for (part, value) in aggregate {
+                        if let Some(value) = value.as_u64() {
+                            metrics.gauges.insert(format!("{}.{}", name, part), value);
+                        }
+                    }
+                },
+                value if field.ends_with("_us") => {
+                    if let Some(value) = value.as_u64() {
+                        metrics.gauges.insert(name, value);
+                    }
+                },
+                value => {
+                    if let Some(value) = value.as_u64() {
+                        *metrics.counters.entry(name).or_insert(0) += value;
+                    }
+                },
+            }
+        }
+    }
+}
+
+// New lines are read from the last offset, so each flush is only counted once.
+fn read_new_lines(workstation: &Workstation, metrics: &mut VmmMetrics) -> Result<(), String> {
+    let path = vmm_metrics_path(workstation);
+
+    let mut file = File::open(&path)
+        .map_err(|e| format!("Failed to open metrics file {}: {}", path, e))?;
+
+    // The file is recreated when a suspended VM is restored.
+    if file.metadata().is_ok_and(|metadata| metadata.len() < metrics.offset) {
+        metrics.offset = 0;
+    }
+
+    file.seek(SeekFrom::Start(metrics.offset))
+        .map_err(|e| format!("Failed to seek metrics file {}: {}", path, e))?;
+
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)
+            .map_err(|e| format!("Failed to read metrics file {}: {}", path, e))?;
+
+        // Stop at a partially written line; it is picked up on the next pass.
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        metrics.offset += read as u64;
+
+        apply_line(metrics, &line);
+    }
+
+    metrics.updated_at = Some(unix_now());
+
+    Ok(())
+}
+
+pub async fn collect_vmm_metrics(state: AppState) {
+    info!("Collecting Firecracker metrics every {} seconds", COLLECT_INTERVAL_SECS);
+
+    let mut interval = tokio::time::interval(Duration::from_secs(COLLECT_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let workstations = state.workstations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+
+        state.vmm_metrics.lock().unwrap().retain(|id, _| workstations.iter().any(|w| &w.id == id));
+
+        for workstation in workstations {
+            if workstation.status != WorkstationStatus::Running {
+                continue;
+            }
+
+            if let Err(e) = flush_metrics(&workstation).await {
+                error!("{}", e);
+                continue;
+            }
+
+            let mut vmm_metrics = state.vmm_metrics.lock().unwrap();
+            let metrics = vmm_metrics.entry(workstation.id.clone()).or_default();
+            if let Err(e) = read_new_lines(&workstation, metrics) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn vmm_metrics_handler(
+    State(state): State<AppState>,
+    Path(workstation_id): Path<String>,
+) -> impl IntoResponse {
+    info!("VMM metrics request received for ID: {}", workstation_id);
+
+    if !state.workstations.lock().unwrap().contains_key(&workstation_id) {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Workstation with ID {} not found", workstation_id)}))).into_response();
+    }
+
+    let metrics = state.vmm_metrics.lock().unwrap()
+        .get(&workstation_id)
+        .cloned()
+        .unwrap_or_default();
+
+    (StatusCode::OK, Json(metrics)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_accumulate_across_flushes() {
+        let mut metrics = VmmMetrics::default();
+
+        apply_line(&mut metrics, r#"{"block_rootfs": {"read_count": 3, "write_bytes": 4096}}"#);
+        apply_line(&mut metrics, r#"{"block_rootfs": {"read_count": 2, "write_bytes": 0}}"#);
+
+        assert_eq!(metrics.counters["block_rootfs.read_count"], 5);
+        assert_eq!(metrics.counters["block_rootfs.write_bytes"], 4096);
+    }
+
+    #[test]
+    fn latency_aggregates_keep_the_latest_value() {
+        let mut metrics = VmmMetrics::default();
+
+        apply_line(&mut metrics, r#"{"vcpu": {"exit_io_in_agg": {"min_us": 4, "max_us": 90, "sum_us": 300}, "kvm_run_us": 50}}"#);
+        apply_line(&mut metrics, r#"{"vcpu": {"exit_io_in_agg": {"min_us": 2, "max_us": 10, "sum_us": 40}, "kvm_run_us": 20}}"#);
+
+        assert_eq!(metrics.gauges["vcpu.exit_io_in_agg.max_us"], 10);
+        assert_eq!(metrics.gauges["vcpu.exit_io_in_agg.sum_us"], 40);
+        assert_eq!(metrics.gauges["vcpu.kvm_run_us"], 20);
+        assert!(metrics.counters.is_empty());
+    }
+
+    #[test]
+    fn other_groups_and_bad_lines_are_ignored() {
+        let mut metrics = VmmMetrics::default();
+
+        apply_line(&mut metrics, r#"{"api_server": {"process_startup_time_us": 10}, "net_eth0": {"tx_count": 1}}"#);
+        apply_line(&mut metrics, "not json");
+
+        assert_eq!(metrics.counters.len(), 1);
+        assert_eq!(metrics.counters["net_eth0.tx_count"], 1);
+        assert!(metrics.gauges.is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    process::Command,
+};
+use tracing::{
+    info,
+    error,
+};
+use uuid::Uuid;
+
+use crate::{AppState, IMAGE_PATH};
+use crate::config::Config;
+use crate::limits::RateLimit;
+use crate::capacity::{
+    BYTES_PER_GIB,
+    check_disk_space,
+};
+use crate::helpers::unix_now;
+
+/*----------------------------------------------------------STRUCTS----------------------------------------------------------*/
+
+// A persistent ext4 image that outlives the workstations it is attached to.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Volume {
+    pub id: String,
+    pub name: Option<String>,
+    pub size_gib: u64,
+    pub created_at: u64,
+    // Attachments only live as long as the workstation, so they are never read back from disk.
+    #[serde(default, skip_deserializing)]
+    pub attached_to: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateVolumePayload {
+    pub name: Option<String>,
+    pub size_gib: u64,
+}
+
+// A volume as requested on workstation creation; attached in order as /dev/vdb, /dev/vdc, ...
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VolumeAttachment {
+    pub id: String,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default, skip_serializing)]
+    pub disk_bandwidth_mbps: Option<u64>,
+    #[serde(default, skip_serializing)]
+    pub disk_iops: Option<u64>,
+    // Resolved from the two fields above and the host defaults when the workstation is created.
+    #[serde(default, skip_deserializing)]
+    pub rate_limit: RateLimit,
+}
+
+impl VolumeAttachment {
+    pub fn with_rate_limit(&self, config: &Config) -> Self {
+        VolumeAttachment {
+            rate_limit: RateLimit::disk(config, self.disk_bandwidth_mbps, self.disk_iops),
+            ..self.clone()
+        }
+    }
+}
+
+fn volumes_dir() -> String {
+    format!("{}volumes/", IMAGE_PATH)
+}
+
+pub fn volume_path(volume_id: &str) -> String {
+    format!("{}{}.ext4", volumes_dir(), volume_id)
+}
+
+fn volume_metadata_path(volume_id: &str) -> String {
+    format!("{}{}.json", volumes_dir(), volume_id)
+}
+
+// Firecracker drive IDs for attached volumes, after the `rootfs` drive.
+pub fn volume_drive_id(index: usize) -> String {
+    format!("vol{}", index + 1)
+}
+
+/*----------------------------------------------------------VOLUMES----------------------------------------------------------*/
+
+// Picks up every volume created by earlier runs.
+pub fn load_volumes() -> HashMap<String, Volume> {
+    let Ok(entries) = fs::read_dir(volumes_dir()) else {
+        return HashMap::new();
+    };
+
+    let volumes = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|entry| {
+            let metadata = fs::read_to_string(entry.path()).ok()?;
+            match serde_json::from_str::<Volume>(&metadata) {
+                Ok(volume) => Some((volume.id.clone(), volume)),
+                Err(e) => {
+                    error!("Skipping unreadable volume metadata {}: {}", entry.path().display(), e);
+                    None
+                }
+            }
+        })
+        .collect::<HashMap<_, _>>();
+
+    info!("Loaded {} volumes from {}", volumes.len(), volumes_dir());
+
+    volumes
+}
+
+fn create_volume_image(volume: &Volume) -> Result<(), String> {
+    let path = volume_path(&volume.id);
+
+    fs::create_dir_all(volumes_dir())
+        .map_err(|e| format!("Failed to create volume directory {}: {}", volumes_dir(), e))?;
+
+    // Sparse: blocks are only allocated as the guest writes them.
+    fs::File::create(&path)
+        .and_then(|file| file.set_len(volume.size_gib * BYTES_PER_GIB))
+        .map_err(|e| format!("Failed to create volume image {}: {}", path, e))?;
+
+    let mkfs = Command::new("mkfs.ext4")
+        .args(["-q", "-F", &path])
+        .status();
+
+    if mkfs.is_err() || !mkfs.unwrap().success() {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Failed to create ext4 filesystem on volume {}", volume.id));
+    }
+
+    if let Err(e) = fs::write(volume_metadata_path(&volume.id), serde_json::to_string_pretty(volume).unwrap()) {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Failed to write metadata for volume {}: {}", volume.id, e));
+    }
+
+    Ok(())
+}
+
+// Claims every requested volume for the workstation, or none of them.
+pub fn attach_volumes(state: &AppState, workstation_id: &str, attachments: &[VolumeAttachment]) -> Result<(), (StatusCode, String)> {
+    let mut volumes = state.volumes.lock().unwrap();
+
+    for attachment in attachments {
+        let Some(volume) = volumes.get(&attachment.id) else {
+            return Err((StatusCode::NOT_FOUND, format!("Volume {} not found", attachment.id)));
+        };
+
+        if let Some(owner) = &volume.attached_to {
+            return Err((StatusCode::CONFLICT, format!("Volume {} is already attached to workstation {}", attachment.id, owner)));
+        }
+    }
+
+    for attachment in attachments {
+        if let Some(volume) = volumes.get_mut(&attachment.id) {
+            volume.attached_to = Some(workstation_id.to_string());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn detach_volumes(state: &AppState, workstation_id: &str) {
+    for volume in state.volumes.lock().unwrap().values_mut() {
+        if volume.attached_to.as_deref() == Some(workstation_id) {
+            volume.attached_to = None;
+        }
+    }
+}
+
+/*----------------------------------------------------------HANDLERS----------------------------------------------------------*/
+
+pub async fn list_volumes_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let mut volumes = state.volumes.lock().unwrap().values().cloned().collect::<Vec<_>>();
+    volumes.sort_by_key(|volume| volume.created_at);
+
+    (StatusCode::OK, Json(volumes))
+}
+
+pub async fn create_volume_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateVolumePayload>,
+) -> impl IntoResponse {
+    info!("Create volume request received.");
+
+    if payload.size_gib == 0 || payload.size_gib > state.config.max_volume_size_gib {
+        let err_msg = format!("Volume size must be between 1 and {} GiB", state.config.max_volume_size_gib);
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": err_msg}))).into_response();
+    }
+
+    // The image is sparse, but the space has to be there once the guest fills it.
+    if let Err(shortage) = check_disk_space(&state, payload.size_gib * BYTES_PER_GIB) {
+        let err_msg = format!(
+            "Not enough host disk for this volume: requested {} bytes, {} bytes available",
+            shortage.requested, shortage.available
+        );
+        return (shortage.status, Json(serde_json::json!({ "error": err_msg, "shortage": shortage }))).into_response();
+    }
+
+    let volume = Volume {
+        id: Uuid::new_v4().to_string(),
+        name: payload.name,
+        size_gib: payload.size_gib,
+        created_at: unix_now(),
+        attached_to: None,
+    };
+
+    let created = tokio::task::spawn_blocking({
+        let volume = volume.clone();
+        move || create_volume_image(&volume)
+    }).await;
+
+    match created {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => {
+            error!("{}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": e}))).into_response();
+        },
+        Err(e) => {
+            error!("Volume creation task panicked: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "Failed to create volume"}))).into_response();
+        }
+    }
+
+    info!("Created volume {} ({} GiB)", volume.id, volume.size_gib);
+
+    state.volumes.lock().unwrap().insert(volume.id.clone(), volume.clone());
+
+    (StatusCode::CREATED, Json(volume)).into_response()
+}
+
+pub async fn get_volume_handler(
+    State(state): State<AppState>,
+    Path(volume_id): Path<String>,
+) -> impl IntoResponse {
+    match state.volumes.lock().unwrap().get(&volume_id) {
+        Some(volume) => (StatusCode::OK, Json(volume.clone())).into_response(),
+        None => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Volume {} not found", volume_id)}))).into_response(),
+    }
+}
+
+pub async fn delete_volume_handler(
+    State(state): State<AppState>,
+    Path(volume_id): Path<String>,
+) -> impl IntoResponse {
+    info!("Delete volume request received for ID: {}", volume_id);
+
+    let mut volumes = state.volumes.lock().unwrap();
+
+    match volumes.get(&volume_id) {
+        None => return (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": format!("Volume {} not found", volume_id)}))).into_response(),
+        Some(Volume { attached_to: Some(owner), .. }) => {
+            let err_msg = format!("Volume {} is attached to workstation {}", volume_id, owner);
+            return (StatusCode::CONFLICT, Json(serde_json::json!({"error": err_msg}))).into_response();
+        },
+        Some(_) => {},
+    }
+
+    if let Err(e) = fs::remove_file(volume_path(&volume_id)) {
+        let err_msg = format!("Failed to delete volume image {}: {}", volume_id, e);
+        error!("{}", err_msg);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": err_msg}))).into_response();
+    }
+    let _ = fs::remove_file(volume_metadata_path(&volume_id));
+
+    volumes.remove(&volume_id);
+
+    (StatusCode::OK, Json(serde_json::json!({"status": format!("Volume {} deleted", volume_id)}))).into_response()
+}